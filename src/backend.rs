@@ -0,0 +1,592 @@
+use crate::db::csr_indexed_db::Store;
+use lemmy_api_common::site::GetSiteResponse;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+// which api an instance speaks, told apart by the version its site reports. 0.19 and earlier serve /api/v3,
+// Lemmy 1.0 and later serve /api/v4 with reshaped views and combined lists
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum ApiVersion {
+  #[default]
+  V3,
+  V4,
+}
+
+impl ApiVersion {
+  pub fn from_version(version: &str) -> Self {
+    match version.trim_start_matches('v').split('.').next().and_then(|m| m.parse::<u32>().ok()) {
+      Some(major) if major >= 1 => ApiVersion::V4,
+      _ => ApiVersion::V3,
+    }
+  }
+
+  pub fn detect(site: &GetSiteResponse) -> Self {
+    Self::from_version(&site.version)
+  }
+
+  pub fn prefix(self) -> &'static str {
+    match self {
+      ApiVersion::V3 => "api/v3",
+      ApiVersion::V4 => "api/v4",
+    }
+  }
+}
+
+// versions seen so far, per instance. requests to an instance not seen yet go to v3 until its site has loaded
+#[cfg(feature = "ssr")]
+mod versions {
+  use super::ApiVersion;
+  use std::{
+    collections::HashMap,
+    sync::{LazyLock, RwLock},
+  };
+
+  static VERSIONS: LazyLock<RwLock<HashMap<String, ApiVersion>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
+
+  pub fn known_version(instance: &str) -> Option<ApiVersion> {
+    VERSIONS.read().ok().and_then(|v| v.get(instance).copied())
+  }
+
+  pub fn remember_version(instance: &str, version: ApiVersion) {
+    if let Ok(mut v) = VERSIONS.write() {
+      v.insert(instance.to_owned(), version);
+    }
+  }
+
+  pub fn forget_version(instance: &str) {
+    if let Ok(mut v) = VERSIONS.write() {
+      v.remove(instance);
+    }
+  }
+}
+
+#[cfg(not(feature = "ssr"))]
+mod versions {
+  use super::ApiVersion;
+  use std::{cell::RefCell, collections::HashMap};
+
+  thread_local! {
+    static VERSIONS: RefCell<HashMap<String, ApiVersion>> = RefCell::new(HashMap::new());
+  }
+
+  pub fn known_version(instance: &str) -> Option<ApiVersion> {
+    VERSIONS.with_borrow(|v| v.get(instance).copied())
+  }
+
+  pub fn remember_version(instance: &str, version: ApiVersion) {
+    VERSIONS.with_borrow_mut(|v| {
+      v.insert(instance.to_owned(), version);
+    });
+  }
+
+  pub fn forget_version(instance: &str) {
+    VERSIONS.with_borrow_mut(|v| {
+      v.remove(instance);
+    });
+  }
+}
+
+pub use versions::{forget_version, known_version, remember_version};
+
+pub fn api_version(instance: &str) -> ApiVersion {
+  known_version(instance).unwrap_or_default()
+}
+
+// translates between the v3 routes, forms and responses the components are written against and what one api
+// version actually serves
+pub trait Backend {
+  fn path(&self, path: &str) -> String;
+  fn form(&self, path: &str, form: Value) -> Value;
+  fn response(&self, path: &str, response: Value) -> Value;
+}
+
+pub struct V3;
+
+impl Backend for V3 {
+  fn path(&self, path: &str) -> String {
+    path.to_owned()
+  }
+
+  fn form(&self, _path: &str, form: Value) -> Value {
+    form
+  }
+
+  fn response(&self, _path: &str, response: Value) -> Value {
+    response
+  }
+}
+
+pub struct V4;
+
+impl Backend for V4 {
+  fn path(&self, path: &str) -> String {
+    match path {
+      "user/login" => "account/auth/login",
+      "user/logout" => "account/auth/logout",
+      "user/block" => "account/block/person",
+      "user/unread_count" => "account/notification/count",
+      "user/replies" | "user/mention" | "private_message/list" => "account/notification/list",
+      "comment/mark_as_read" => "account/notification/mark_as_read",
+      "user" => "person",
+      p => p,
+    }
+    .to_owned()
+  }
+
+  fn form(&self, path: &str, form: Value) -> Value {
+    let Value::Object(mut form) = form else {
+      return form;
+    };
+    // v4 rejects explicit nulls in query strings
+    form.retain(|_, v| !v.is_null());
+    match path {
+      "post/like" | "comment/like" => {
+        let is_upvote = match form.get("score").and_then(Value::as_i64) {
+          Some(1) => json!(true),
+          Some(-1) => json!(false),
+          _ => Value::Null,
+        };
+        form.insert("is_upvote".into(), is_upvote);
+      }
+      "post/list" => {
+        // top sorts lost their time range suffix, which became a separate parameter
+        if let Some(sort) = form.get("sort").and_then(Value::as_str).map(str::to_owned) {
+          if let Some((sort, seconds)) = top_sort(&sort) {
+            form.insert("sort".into(), json!(sort));
+            form.insert("time_range_seconds".into(), json!(seconds));
+          }
+        }
+      }
+      "user/replies" => {
+        form.insert("type_".into(), json!("Reply"));
+      }
+      "user/mention" => {
+        form.insert("type_".into(), json!("Mention"));
+      }
+      "private_message/list" => {
+        form.insert("type_".into(), json!("PrivateMessage"));
+      }
+      "comment/mark_as_read" => {
+        if let Some(id) = form.remove("comment_reply_id") {
+          form.insert("notification_id".into(), id);
+        }
+      }
+      _ => {}
+    }
+    Value::Object(form)
+  }
+
+  fn response(&self, path: &str, response: Value) -> Value {
+    let mut response = rename_fields(response);
+    match path {
+      "post/list" => json!({ "posts": items(&mut response).into_iter().map(post_view).collect::<Vec<_>>(), "next_page": response["next_page"] }),
+      "comment/list" => json!({ "comments": items(&mut response).into_iter().map(comment_view).collect::<Vec<_>>() }),
+      "post" => {
+        let mut r = response;
+        r["post_view"] = post_view(r["post_view"].take());
+        r["cross_posts"] = Value::Array(take_array(&mut r["cross_posts"]).into_iter().map(post_view).collect());
+        default_fields(&mut r, &[("moderators", json!([]))]);
+        r
+      }
+      "post/like" | "post/save" => {
+        let mut r = response;
+        r["post_view"] = post_view(r["post_view"].take());
+        r
+      }
+      "comment" | "comment/like" | "comment/save" => {
+        let mut r = response;
+        r["comment_view"] = comment_view(r["comment_view"].take());
+        default_fields(&mut r, &[("recipient_ids", json!([]))]);
+        r
+      }
+      "search" => search(response),
+      "user/replies" => json!({ "replies": notifications(&mut response, "Comment").into_iter().map(reply_view).collect::<Vec<_>>() }),
+      "user/mention" => json!({ "mentions": notifications(&mut response, "Comment").into_iter().map(reply_view).collect::<Vec<_>>() }),
+      "private_message/list" => {
+        json!({ "private_messages": notifications(&mut response, "PrivateMessage").into_iter().map(|(_, data)| data).collect::<Vec<_>>() })
+      }
+      // only the total is counted, the kinds stay unknown
+      "user/unread_count" => json!({ "total": response["count"].as_i64().unwrap_or(0) }),
+      // posts and comments are filled in from person/content
+      "user" => {
+        let mut r = response;
+        default_fields(&mut r, &[("comments", json!([])), ("posts", json!([])), ("moderates", json!([]))]);
+        r
+      }
+      "person/content" => {
+        let (mut posts, mut comments) = (vec![], vec![]);
+        for mut item in items(&mut response) {
+          match item["type_"].take().as_str() {
+            Some("Post") => posts.push(post_view(item)),
+            Some("Comment") => comments.push(comment_view(item)),
+            _ => {}
+          }
+        }
+        json!({ "posts": posts, "comments": comments })
+      }
+      "account" => {
+        let mut r = response;
+        let person = &r["local_user_view"]["person"];
+        let counts = json!({
+          "person_id": person["id"],
+          "post_count": person["post_count"].as_i64().unwrap_or(0),
+          "comment_count": person["comment_count"].as_i64().unwrap_or(0),
+        });
+        default_fields(&mut r["local_user_view"], &[("counts", counts), ("local_user_vote_display_mode", Value::Null)]);
+        default_fields(
+          &mut r,
+          &[
+            ("follows", json!([])),
+            ("moderates", json!([])),
+            ("community_blocks", json!([])),
+            ("instance_blocks", json!([])),
+            ("person_blocks", json!([])),
+            ("discussion_languages", json!([])),
+          ],
+        );
+        r
+      }
+      "site" => {
+        let mut r = response;
+        default_fields(&mut r, &[("taglines", json!([])), ("custom_emojis", json!([])), ("blocked_urls", json!([]))]);
+        r
+      }
+      _ => response,
+    }
+  }
+}
+
+pub fn backend(version: ApiVersion) -> &'static dyn Backend {
+  match version {
+    ApiVersion::V3 => &V3,
+    ApiVersion::V4 => &V4,
+  }
+}
+
+// a v4 form sent through the same fetch path as the typed v3 ones
+#[derive(Clone, Debug, Serialize)]
+#[serde(transparent)]
+pub struct BackendForm {
+  pub form: Value,
+  #[serde(skip)]
+  pub store: &'static str,
+}
+
+impl Store for BackendForm {
+  fn store_name(&self) -> &'static str {
+    self.store
+  }
+}
+
+fn top_sort(sort: &str) -> Option<(&'static str, i64)> {
+  const HOUR: i64 = 60 * 60;
+  const DAY: i64 = 24 * HOUR;
+  Some(match sort {
+    "TopHour" => ("Top", HOUR),
+    "TopSixHour" => ("Top", 6 * HOUR),
+    "TopTwelveHour" => ("Top", 12 * HOUR),
+    "TopDay" => ("Top", DAY),
+    "TopWeek" => ("Top", 7 * DAY),
+    "TopMonth" => ("Top", 30 * DAY),
+    "TopThreeMonths" => ("Top", 90 * DAY),
+    "TopSixMonths" => ("Top", 180 * DAY),
+    "TopNineMonths" => ("Top", 270 * DAY),
+    "TopYear" => ("Top", 365 * DAY),
+    _ => return None,
+  })
+}
+
+fn take_array(value: &mut Value) -> Vec<Value> {
+  match value.take() {
+    Value::Array(a) => a,
+    _ => vec![],
+  }
+}
+
+// v4 list endpoints page their results under `items`
+fn items(response: &mut Value) -> Vec<Value> {
+  take_array(&mut response["items"])
+}
+
+fn default_fields(value: &mut Value, defaults: &[(&str, Value)]) {
+  if let Value::Object(o) = value {
+    for (k, v) in defaults {
+      o.entry(*k).or_insert_with(|| v.clone());
+    }
+  }
+}
+
+// field names v4 changed throughout, mapped back to their v3 spelling
+fn rename_fields(value: Value) -> Value {
+  let rename = |k: String| match k.as_str() {
+    "ap_id" => "actor_id".to_owned(),
+    "published_at" => "published".to_owned(),
+    "updated_at" => "updated".to_owned(),
+    "newest_comment_time_at" => "newest_comment_time".to_owned(),
+    _ => k,
+  };
+  match value {
+    Value::Object(o) => Value::Object(o.into_iter().map(|(k, v)| (rename(k), rename_fields(v))).collect()),
+    Value::Array(a) => Value::Array(a.into_iter().map(rename_fields).collect()),
+    v => v,
+  }
+}
+
+fn my_vote(actions: &Value) -> Value {
+  match actions["vote_is_upvote"].as_bool() {
+    Some(true) => json!(1),
+    Some(false) => json!(-1),
+    None => Value::Null,
+  }
+}
+
+fn subscribed(community_actions: &Value) -> Value {
+  match community_actions["follow_state"].as_str() {
+    Some("Accepted") => json!("Subscribed"),
+    Some("Pending") | Some("ApprovalRequired") => json!("Pending"),
+    _ => json!("NotSubscribed"),
+  }
+}
+
+// flags v4 dropped from post and comment views, or only sends when set
+const VIEW_FLAGS: [&str; 4] = ["creator_banned_from_community", "banned_from_community", "creator_is_moderator", "creator_is_admin"];
+
+// v4 folds aggregates into the post and moves the viewer's vote, save and read state into `post_actions`
+fn post_view(mut v: Value) -> Value {
+  if !v.is_object() {
+    return v;
+  }
+  let post = &v["post"];
+  let counts = json!({
+    "post_id": post["id"],
+    "comments": post["comments"].as_i64().unwrap_or(0),
+    "score": post["score"].as_i64().unwrap_or(0),
+    "upvotes": post["upvotes"].as_i64().unwrap_or(0),
+    "downvotes": post["downvotes"].as_i64().unwrap_or(0),
+    "published": post["published"],
+    "newest_comment_time": post["newest_comment_time"],
+  });
+  let actions = v["post_actions"].take();
+  let community_actions = v["community_actions"].take();
+  let person_actions = v["person_actions"].take();
+  let comments = counts["comments"].as_i64().unwrap_or(0);
+  let read_comments = actions["read_comments_amount"].as_i64().unwrap_or(comments);
+  if let Some(o) = v.as_object_mut() {
+    o.insert("counts".into(), counts);
+    o.insert("my_vote".into(), my_vote(&actions));
+    o.insert("saved".into(), json!(!actions["saved_at"].is_null()));
+    o.insert("read".into(), json!(!actions["read_at"].is_null()));
+    o.insert("hidden".into(), json!(!actions["hidden_at"].is_null()));
+    o.insert("subscribed".into(), subscribed(&community_actions));
+    o.insert("creator_blocked".into(), json!(!person_actions["blocked_at"].is_null()));
+    o.insert("unread_comments".into(), json!((comments - read_comments).max(0)));
+    for k in VIEW_FLAGS {
+      o.entry(k).or_insert(json!(false));
+    }
+  }
+  v
+}
+
+fn comment_view(mut v: Value) -> Value {
+  if !v.is_object() {
+    return v;
+  }
+  let comment = &v["comment"];
+  let counts = json!({
+    "comment_id": comment["id"],
+    "score": comment["score"].as_i64().unwrap_or(0),
+    "upvotes": comment["upvotes"].as_i64().unwrap_or(0),
+    "downvotes": comment["downvotes"].as_i64().unwrap_or(0),
+    "published": comment["published"],
+    "child_count": comment["child_count"].as_i64().unwrap_or(0),
+  });
+  let actions = v["comment_actions"].take();
+  let community_actions = v["community_actions"].take();
+  let person_actions = v["person_actions"].take();
+  if let Some(o) = v.as_object_mut() {
+    o.insert("counts".into(), counts);
+    o.insert("my_vote".into(), my_vote(&actions));
+    o.insert("saved".into(), json!(!actions["saved_at"].is_null()));
+    o.insert("subscribed".into(), subscribed(&community_actions));
+    o.insert("creator_blocked".into(), json!(!person_actions["blocked_at"].is_null()));
+    for k in VIEW_FLAGS {
+      o.entry(k).or_insert(json!(false));
+    }
+  }
+  v
+}
+
+// the combined search returns one list tagged by `type_`, v3 had a list per kind
+fn search(mut response: Value) -> Value {
+  let (mut posts, mut comments, mut communities, mut users) = (vec![], vec![], vec![], vec![]);
+  for mut item in items(&mut response) {
+    let kind = item["type_"].take();
+    match kind.as_str() {
+      Some("Post") => posts.push(post_view(item)),
+      Some("Comment") => comments.push(comment_view(item)),
+      Some("Community") => communities.push(item),
+      Some("Person") => users.push(item),
+      _ => {}
+    }
+  }
+  json!({ "type_": "All", "posts": posts, "comments": comments, "communities": communities, "users": users })
+}
+
+// replies, mentions and private messages all arrive as notifications, each wrapping the thing it is about
+fn notifications(response: &mut Value, kind: &str) -> Vec<(Value, Value)> {
+  items(response)
+    .into_iter()
+    .filter_map(|mut n| {
+      let mut data = n["data"].take();
+      (data["type_"].take().as_str() == Some(kind)).then(|| (n["notification"].take(), data))
+    })
+    .collect()
+}
+
+fn reply_view((notification, data): (Value, Value)) -> Value {
+  let mut v = comment_view(data);
+  v["comment_reply"] = json!({
+    "id": notification["id"],
+    "recipient_id": notification["recipient_id"],
+    "comment_id": v["comment"]["id"],
+    "read": notification["read"].as_bool().unwrap_or(false),
+    "published": notification["published"],
+  });
+  if v["recipient"].is_null() {
+    v["recipient"] = v["creator"].clone();
+  }
+  v
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn versions_from_site() {
+    for (version, api) in [
+      ("0.19.5", ApiVersion::V3),
+      ("v0.18.0", ApiVersion::V3),
+      ("1.0.0-alpha.4", ApiVersion::V4),
+      ("v1.2.3", ApiVersion::V4),
+      ("2.0", ApiVersion::V4),
+      ("", ApiVersion::V3),
+      ("unknown", ApiVersion::V3),
+    ] {
+      assert_eq!(ApiVersion::from_version(version), api, "{}", version);
+    }
+  }
+
+  #[test]
+  fn v4_paths() {
+    for (v3, v4) in [
+      ("post/list", "post/list"),
+      ("comment/list", "comment/list"),
+      ("site", "site"),
+      ("user", "person"),
+      ("person/content", "person/content"),
+      ("user/login", "account/auth/login"),
+      ("user/unread_count", "account/notification/count"),
+      ("user/replies", "account/notification/list"),
+      ("user/mention", "account/notification/list"),
+      ("private_message/list", "account/notification/list"),
+      ("comment/mark_as_read", "account/notification/mark_as_read"),
+    ] {
+      assert_eq!(V4.path(v3), v4, "{}", v3);
+    }
+  }
+
+  #[test]
+  fn v4_forms() {
+    for (path, form, expected) in [
+      ("post/list", json!({ "sort": "TopWeek", "page_cursor": null }), json!({ "sort": "Top", "time_range_seconds": 7 * 24 * 60 * 60 })),
+      ("post/list", json!({ "sort": "Hot", "limit": 10 }), json!({ "sort": "Hot", "limit": 10 })),
+      ("comment/list", json!({ "post_id": 1, "parent_id": null }), json!({ "post_id": 1 })),
+      ("post/like", json!({ "post_id": 1, "score": -1 }), json!({ "post_id": 1, "score": -1, "is_upvote": false })),
+      ("comment/like", json!({ "comment_id": 1, "score": 0 }), json!({ "comment_id": 1, "score": 0, "is_upvote": null })),
+      ("user/replies", json!({ "unread_only": true }), json!({ "unread_only": true, "type_": "Reply" })),
+      ("private_message/list", json!({}), json!({ "type_": "PrivateMessage" })),
+      ("comment/mark_as_read", json!({ "comment_reply_id": 5, "read": true }), json!({ "notification_id": 5, "read": true })),
+      ("site", json!({ "t": 1000 }), json!({ "t": 1000 })),
+    ] {
+      assert_eq!(V4.form(path, form), expected, "{}", path);
+    }
+  }
+
+  fn v4_post(id: i64) -> Value {
+    json!({
+      "post": { "id": id, "ap_id": "https://example.org/post/1", "published_at": "2025-01-01T00:00:00Z", "comments": 3, "score": 5 },
+      "post_actions": { "vote_is_upvote": true, "saved_at": "2025-01-02T00:00:00Z", "read_comments_amount": 1 },
+      "community_actions": { "follow_state": "Accepted" },
+    })
+  }
+
+  fn v4_comment(id: i64) -> Value {
+    json!({ "comment": { "id": id, "score": 2, "child_count": 1 }, "comment_actions": { "vote_is_upvote": false } })
+  }
+
+  #[test]
+  fn v4_responses() {
+    let posts = V4.response("post/list", json!({ "items": [v4_post(1)], "next_page": "abc" }));
+    let post = &posts["posts"][0];
+    assert_eq!(posts["next_page"], "abc");
+    assert_eq!(post["post"]["actor_id"], "https://example.org/post/1");
+    assert_eq!(post["post"]["published"], "2025-01-01T00:00:00Z");
+    assert_eq!(post["counts"]["comments"], 3);
+    assert_eq!(post["counts"]["score"], 5);
+    assert_eq!(post["my_vote"], 1);
+    assert_eq!(post["saved"], true);
+    assert_eq!(post["read"], false);
+    assert_eq!(post["subscribed"], "Subscribed");
+    assert_eq!(post["unread_comments"], 2);
+    assert_eq!(post["creator_is_admin"], false);
+
+    let comments = V4.response("comment/list", json!({ "items": [v4_comment(2)] }));
+    let comment = &comments["comments"][0];
+    assert_eq!(comment["counts"]["child_count"], 1);
+    assert_eq!(comment["my_vote"], -1);
+    assert_eq!(comment["subscribed"], "NotSubscribed");
+
+    let person = V4.response("user", json!({ "person_view": { "person": { "id": 3 } } }));
+    assert_eq!(person["posts"], json!([]));
+    assert_eq!(person["comments"], json!([]));
+    assert_eq!(person["moderates"], json!([]));
+
+    let content = V4.response(
+      "person/content",
+      json!({ "items": [
+        { "type_": "Post", "post": { "id": 1 } },
+        { "type_": "Comment", "comment": { "id": 2 } },
+        { "type_": "Community" },
+      ] }),
+    );
+    assert_eq!(content["posts"].as_array().map(Vec::len), Some(1));
+    assert_eq!(content["comments"][0]["comment"]["id"], 2);
+
+    let site = V4.response("site", json!({ "version": "1.0.0" }));
+    assert_eq!(site["version"], "1.0.0");
+    assert_eq!(site["taglines"], json!([]));
+    assert_eq!(site["custom_emojis"], json!([]));
+  }
+
+  #[test]
+  fn v4_inbox_splits_by_kind() {
+    let inbox = json!({ "items": [
+      { "notification": { "id": 10, "read": true }, "data": { "type_": "Comment", "comment": { "id": 2 }, "creator": { "id": 4 } } },
+      { "notification": { "id": 11 }, "data": { "type_": "PrivateMessage", "private_message": { "id": 7 } } },
+    ] });
+    let replies = V4.response("user/replies", inbox.clone());
+    assert_eq!(replies["replies"].as_array().map(Vec::len), Some(1));
+    assert_eq!(replies["replies"][0]["comment_reply"]["id"], 10);
+    assert_eq!(replies["replies"][0]["comment_reply"]["comment_id"], 2);
+    assert_eq!(replies["replies"][0]["comment_reply"]["read"], true);
+    assert_eq!(replies["replies"][0]["recipient"]["id"], 4);
+
+    let messages = V4.response("private_message/list", inbox);
+    assert_eq!(messages["private_messages"].as_array().map(Vec::len), Some(1));
+    assert_eq!(messages["private_messages"][0]["private_message"]["id"], 7);
+
+    let count = V4.response("user/unread_count", json!({ "count": 4 }));
+    assert_eq!(count, json!({ "total": 4 }));
+  }
+}
@@ -0,0 +1,433 @@
+// the same typed api the app uses, from a terminal or cron: cargo run --no-default-features --features cli -- --help
+#![allow(warnings)]
+
+use aos::{
+  client::{InstanceClient, LemmyApi, UnreadCounts},
+  errors::{LemmyAppError, LemmyAppResult, error_message},
+  instances::normalize_host,
+};
+use clap::{Parser, Subcommand};
+use lemmy_api_common::{
+  comment::GetComments,
+  lemmy_db_schema::{
+    CommentSortType, ListingType, SearchType, SortType,
+    newtypes::PostId,
+    source::{community::Community, person::Person},
+  },
+  lemmy_db_views::structs::{CommentView, PostView},
+  person::{GetPersonMentions, GetPersonMentionsResponse, GetReplies, GetRepliesResponse, Login},
+  post::{GetPost, GetPosts},
+  private_message::{GetPrivateMessages, PrivateMessagesResponse},
+  site::Search,
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+  io::{self, BufRead},
+  path::PathBuf,
+  process::ExitCode,
+};
+
+// saved items are walked a page at a time until a short page comes back
+const SAVED_PAGE: i64 = 50;
+
+#[derive(Parser)]
+#[command(name = "aos-cli", about = "Read and script a Lemmy instance through the AOS api client")]
+struct Cli {
+  #[arg(long, global = true, help = "Instance to use instead of the one in the config file")]
+  instance: Option<String>,
+  #[arg(long, global = true, help = "Config file, defaults to $AOS_CLI_CONFIG or ~/.config/aos/cli.json")]
+  config: Option<PathBuf>,
+  #[arg(long, global = true, help = "Print responses as json instead of plain text")]
+  json: bool,
+  #[command(subcommand)]
+  command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  #[command(about = "Log in and keep the token in the config file, the password is read from $AOS_PASSWORD or stdin")]
+  Login {
+    username: String,
+    #[arg(long)]
+    totp: Option<String>,
+  },
+  #[command(about = "Log out and forget the token")]
+  Logout,
+  #[command(about = "List posts, from one community or a whole listing")]
+  Posts {
+    #[arg(long)]
+    community: Option<String>,
+    #[arg(long, default_value = "Active")]
+    sort: String,
+    #[arg(long, default_value = "All")]
+    list: String,
+    #[arg(long, default_value_t = 20)]
+    limit: i64,
+    #[arg(long)]
+    page: Option<i64>,
+  },
+  #[command(about = "Show a post and its comments")]
+  Thread {
+    post_id: i32,
+    #[arg(long, default_value = "Hot")]
+    sort: String,
+  },
+  #[command(about = "Search posts, comments, communities and users")]
+  Search {
+    term: String,
+    #[arg(long, default_value = "All")]
+    kind: String,
+    #[arg(long, default_value = "TopAll")]
+    sort: String,
+    #[arg(long, default_value_t = 20)]
+    limit: i64,
+    #[arg(long)]
+    page: Option<i64>,
+  },
+  #[command(about = "Show replies, mentions and private messages")]
+  Inbox {
+    #[arg(long, help = "Only what has not been read yet")]
+    unread: bool,
+  },
+  #[command(about = "Export every saved post and comment")]
+  Saved,
+}
+
+// what the cli remembers between runs
+#[derive(Default, Serialize, Deserialize)]
+struct Config {
+  instance: Option<String>,
+  jwt: Option<String>,
+}
+
+fn config_path(explicit: Option<PathBuf>) -> PathBuf {
+  explicit.or_else(|| std::env::var_os("AOS_CLI_CONFIG").map(PathBuf::from)).unwrap_or_else(|| {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+      .map(PathBuf::from)
+      .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+      .unwrap_or_default();
+    base.join("aos").join("cli.json")
+  })
+}
+
+fn load_config(path: &PathBuf) -> Result<Config, String> {
+  match std::fs::read_to_string(path) {
+    Ok(s) => serde_json::from_str(&s).map_err(|e| format!("{}: {}", path.display(), e)),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+    Err(e) => Err(format!("{}: {}", path.display(), e)),
+  }
+}
+
+// the file holds a login token, so only its owner may read it
+fn save_config(path: &PathBuf, config: &Config) -> Result<(), String> {
+  let failed = |e: io::Error| format!("{}: {}", path.display(), e);
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir).map_err(failed)?;
+  }
+  std::fs::write(path, serde_json::to_string_pretty(config).unwrap_or_default()).map_err(failed)?;
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).map_err(failed)?;
+  }
+  Ok(())
+}
+
+// sorts and listings are spelled as the api spells them, e.g. TopWeek or Subscribed
+fn parse<T: DeserializeOwned>(name: &str, value: &str) -> Result<T, String> {
+  serde_json::from_value(serde_json::Value::String(value.to_owned())).map_err(|_| format!("unknown {} {}", name, value))
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), String> {
+  println!("{}", serde_json::to_string_pretty(value).map_err(|e| e.to_string())?);
+  Ok(())
+}
+
+fn community_handle(community: &Community) -> String {
+  format!("!{}@{}", community.name, community.actor_id.inner().host_str().unwrap_or_default())
+}
+
+fn person_handle(person: &Person) -> String {
+  format!("@{}@{}", person.name, person.actor_id.inner().host_str().unwrap_or_default())
+}
+
+fn first_line(text: &str) -> &str {
+  text.lines().find(|l| !l.trim().is_empty()).unwrap_or("").trim()
+}
+
+fn print_post(p: &PostView) {
+  println!("{}\t{}\t{}\t{}\t{}", p.post.id.0, p.counts.score, p.counts.comments, community_handle(&p.community), p.post.name);
+}
+
+fn print_comment(c: &CommentView) {
+  println!("{}\t{}\t{}\t{}\t{}", c.comment.id.0, c.counts.score, c.post.id.0, person_handle(&c.creator), first_line(&c.comment.content));
+}
+
+fn error_text(e: LemmyAppError) -> String {
+  format!("{} ({})", error_message(&e), e.content)
+}
+
+#[derive(Serialize)]
+struct Inbox {
+  unread: UnreadCounts,
+  replies: GetRepliesResponse,
+  mentions: GetPersonMentionsResponse,
+  messages: PrivateMessagesResponse,
+}
+
+#[derive(Serialize)]
+struct Saved {
+  posts: Vec<PostView>,
+  comments: Vec<CommentView>,
+}
+
+async fn saved(client: &InstanceClient) -> LemmyAppResult<Saved> {
+  let mut saved = Saved { posts: vec![], comments: vec![] };
+  for page in 1.. {
+    let form = GetPosts {
+      type_: Some(ListingType::All),
+      sort: Some(SortType::New),
+      community_name: None,
+      community_id: None,
+      page: Some(page),
+      limit: Some(SAVED_PAGE),
+      saved_only: Some(true),
+      disliked_only: None,
+      liked_only: None,
+      page_cursor: None,
+      show_hidden: Some(true),
+      show_nsfw: Some(true),
+      show_read: Some(true),
+    };
+    let posts = client.list_posts(form).await?.posts;
+    let done = (posts.len() as i64) < SAVED_PAGE;
+    saved.posts.extend(posts);
+    if done {
+      break;
+    }
+  }
+  for page in 1.. {
+    let form = GetComments {
+      post_id: None,
+      community_id: None,
+      type_: Some(ListingType::All),
+      sort: Some(CommentSortType::New),
+      max_depth: None,
+      page: Some(page),
+      limit: Some(SAVED_PAGE),
+      community_name: None,
+      parent_id: None,
+      saved_only: Some(true),
+      disliked_only: None,
+      liked_only: None,
+    };
+    let comments = client.get_comments(form).await?.comments;
+    let done = (comments.len() as i64) < SAVED_PAGE;
+    saved.comments.extend(comments);
+    if done {
+      break;
+    }
+  }
+  Ok(saved)
+}
+
+async fn run(cli: Cli) -> Result<(), String> {
+  let path = config_path(cli.config);
+  let mut config = load_config(&path)?;
+  let instance = match cli.instance.or(config.instance.clone()) {
+    Some(i) => normalize_host(&i).ok_or_else(|| format!("{} is not a valid instance address", i))?,
+    None => return Err("no instance, pass --instance or log in first".into()),
+  };
+  // a token only belongs to the instance that issued it
+  let jwt = if config.instance.as_deref() == Some(instance.as_str()) { config.jwt.clone() } else { None };
+  let client = InstanceClient::new(instance.clone(), jwt);
+  // which api the instance speaks is only known once its site has been asked for. logging out forgets the token
+  // even when the instance cannot be reached
+  if let Err(e) = client.get_site().await
+    && !matches!(cli.command, Command::Logout)
+  {
+    return Err(error_text(e));
+  }
+
+  match cli.command {
+    Command::Login { username, totp } => {
+      let password = match std::env::var("AOS_PASSWORD") {
+        Ok(p) => p,
+        Err(_) => {
+          let mut line = String::new();
+          io::stdin().lock().read_line(&mut line).map_err(|e| e.to_string())?;
+          line.trim_end_matches(['\r', '\n']).to_owned()
+        }
+      };
+      let form = Login { username_or_email: username.into(), password: password.into(), totp_2fa_token: totp };
+      let response = client.login(form).await.map_err(error_text)?;
+      let Some(jwt) = response.jwt else {
+        return Err("the instance did not return a token, the account may still need verifying".into());
+      };
+      config.instance = Some(instance.clone());
+      config.jwt = Some(jwt.into_inner());
+      save_config(&path, &config)?;
+      if !cli.json {
+        println!("logged in to {}", instance);
+      }
+    }
+    Command::Logout => {
+      if client.jwt.is_some() {
+        _ = client.logout().await;
+      }
+      config.jwt = None;
+      save_config(&path, &config)?;
+    }
+    Command::Posts { community, sort, list, limit, page } => {
+      let form = GetPosts {
+        type_: Some(parse("listing", &list)?),
+        sort: Some(parse("sort", &sort)?),
+        community_name: community,
+        community_id: None,
+        page,
+        limit: Some(limit),
+        saved_only: None,
+        disliked_only: None,
+        liked_only: None,
+        page_cursor: None,
+        show_hidden: Some(true),
+        show_nsfw: Some(false),
+        show_read: Some(true),
+      };
+      let response = client.list_posts(form).await.map_err(error_text)?;
+      if cli.json {
+        print_json(&response)?;
+      } else {
+        response.posts.iter().for_each(print_post);
+      }
+    }
+    Command::Thread { post_id, sort } => {
+      let post = client.get_post(GetPost { id: Some(PostId(post_id)), comment_id: None }).await.map_err(error_text)?;
+      let form = GetComments {
+        post_id: Some(PostId(post_id)),
+        community_id: None,
+        type_: None,
+        sort: Some(parse("sort", &sort)?),
+        max_depth: Some(128),
+        page: None,
+        limit: None,
+        community_name: None,
+        parent_id: None,
+        saved_only: None,
+        disliked_only: None,
+        liked_only: None,
+      };
+      let mut comments = client.get_comments(form).await.map_err(error_text)?;
+      if cli.json {
+        print_json(&serde_json::json!({ "post": post, "comments": comments }))?;
+      } else {
+        let p = &post.post_view;
+        println!("{}\n{} in {}, {} points", p.post.name, person_handle(&p.creator), community_handle(&p.community), p.counts.score);
+        if let Some(url) = &p.post.url {
+          println!("{}", url.inner());
+        }
+        if let Some(body) = &p.post.body {
+          println!("\n{}", body);
+        }
+        // paths run from the root "0", so sorting by them puts every reply under its parent
+        comments.comments.sort_by(|a, b| a.comment.path.cmp(&b.comment.path));
+        for c in &comments.comments {
+          let depth = c.comment.path.split('.').count().saturating_sub(2);
+          println!("\n{}{} ({}, {} points)", "  ".repeat(depth), person_handle(&c.creator), c.comment.id.0, c.counts.score);
+          for line in c.comment.content.lines() {
+            println!("{}{}", "  ".repeat(depth), line);
+          }
+        }
+      }
+    }
+    Command::Search { term, kind, sort, limit, page } => {
+      let form = Search {
+        q: term,
+        type_: Some(parse::<SearchType>("kind", &kind)?),
+        sort: Some(parse("sort", &sort)?),
+        community_name: None,
+        community_id: None,
+        page,
+        limit: Some(limit),
+        creator_id: None,
+        listing_type: None,
+        post_title_only: None,
+      };
+      let response = client.search(form).await.map_err(error_text)?;
+      if cli.json {
+        print_json(&response)?;
+      } else {
+        response.posts.iter().for_each(print_post);
+        response.comments.iter().for_each(print_comment);
+        for c in &response.communities {
+          println!("{}\t{}\t{}", c.community.id.0, c.counts.subscribers, community_handle(&c.community));
+        }
+        for u in &response.users {
+          println!("{}\t{}\t{}", u.person.id.0, u.counts.post_count, person_handle(&u.person));
+        }
+      }
+    }
+    Command::Inbox { unread } => {
+      if client.jwt.is_none() {
+        return Err(format!("not logged in to {}", instance));
+      }
+      let unread_only = Some(unread);
+      let inbox = Inbox {
+        unread: client.unread_count().await.map_err(error_text)?,
+        replies: client
+          .replies_user(GetReplies { sort: Some(CommentSortType::New), page: None, limit: Some(SAVED_PAGE), unread_only })
+          .await
+          .map_err(error_text)?,
+        mentions: client
+          .mention_user(GetPersonMentions { sort: Some(CommentSortType::New), page: None, limit: Some(SAVED_PAGE), unread_only })
+          .await
+          .map_err(error_text)?,
+        messages: client
+          .messages_user(GetPrivateMessages { unread_only, page: None, limit: Some(SAVED_PAGE), creator_id: None })
+          .await
+          .map_err(error_text)?,
+      };
+      if cli.json {
+        print_json(&inbox)?;
+      } else {
+        match (inbox.unread.replies, inbox.unread.mentions, inbox.unread.private_messages) {
+          (Some(replies), Some(mentions), Some(messages)) => println!("{} replies, {} mentions, {} messages unread", replies, mentions, messages),
+          _ => println!("{} unread", inbox.unread.total),
+        }
+        for r in &inbox.replies.replies {
+          println!("reply\t{}\t{}\t{}", r.comment.id.0, person_handle(&r.creator), first_line(&r.comment.content));
+        }
+        for m in &inbox.mentions.mentions {
+          println!("mention\t{}\t{}\t{}", m.comment.id.0, person_handle(&m.creator), first_line(&m.comment.content));
+        }
+        for m in &inbox.messages.private_messages {
+          println!("message\t{}\t{}\t{}", m.private_message.id.0, person_handle(&m.creator), first_line(&m.private_message.content));
+        }
+      }
+    }
+    Command::Saved => {
+      if client.jwt.is_none() {
+        return Err(format!("not logged in to {}", instance));
+      }
+      let saved = saved(&client).await.map_err(error_text)?;
+      if cli.json {
+        print_json(&saved)?;
+      } else {
+        saved.posts.iter().for_each(print_post);
+        saved.comments.iter().for_each(print_comment);
+      }
+    }
+  }
+  Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+  match run(Cli::parse()).await {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(e) => {
+      eprintln!("aos-cli: {}", e);
+      ExitCode::FAILURE
+    }
+  }
+}
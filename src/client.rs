@@ -1,12 +1,19 @@
 use crate::{
   ReadAuthCookie, ReadInstanceCookie, WriteAuthCookie, WriteInstanceCookie,
+  backend::{ApiVersion, BackendForm, api_version, backend, forget_version, known_version, remember_version},
   db::csr_indexed_db::*,
+  entities::{record, recording},
   errors::{LemmyAppError, LemmyAppErrorType, LemmyAppResult},
+  fixtures::fixtures,
+  pending::queue_when_offline,
+  query_cache::invalidate_on_success,
+  server_config::visitor_instance,
 };
 use lemmy_api_common::{
   LemmyErrorType, SuccessResponse,
   comment::*,
   community::*,
+  lemmy_db_views::structs::{CommentView, PostView},
   person::*,
   post::*,
   private_message::{GetPrivateMessages, PrivateMessagesResponse},
@@ -14,7 +21,7 @@ use lemmy_api_common::{
 };
 use leptos::{logging::log, prelude::*};
 use send_wrapper::SendWrapper;
-use serde::{Serialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use std::str;
 
 #[derive(Clone, PartialEq)]
@@ -27,13 +34,124 @@ pub enum HttpType {
   Put,
 }
 
+// the app's client, sending requests to the instance and with the login held in the cookies of the current owner
 pub struct LemmyClient;
 
+// a client for one instance and login that needs no reactive owner, for background tasks, tests and other binaries
+#[derive(Clone)]
+pub struct InstanceClient {
+  pub instance: String,
+  pub jwt: Option<String>,
+  // none to go through the server's shared client
+  #[cfg(feature = "ssr")]
+  pub http: Option<reqwest::Client>,
+}
+
+// how the server talks to instances. one client is shared by every request so connections and tls sessions are
+// pooled, and the timeouts keep a slow instance from holding a render open
+#[cfg(feature = "ssr")]
+#[derive(Clone, Debug)]
+pub struct HttpConfig {
+  pub connect_timeout: std::time::Duration,
+  // between reads of the response, so a large but steady response is not cut off
+  pub read_timeout: std::time::Duration,
+  // the whole request, retries aside
+  pub timeout: std::time::Duration,
+  pub user_agent: String,
+}
+
+#[cfg(feature = "ssr")]
+impl Default for HttpConfig {
+  fn default() -> Self {
+    Self {
+      connect_timeout: std::time::Duration::from_secs(5),
+      read_timeout: std::time::Duration::from_secs(10),
+      timeout: std::time::Duration::from_secs(20),
+      user_agent: format!("AOS/{} (Lemmy client)", env!("CARGO_PKG_VERSION")),
+    }
+  }
+}
+
+#[cfg(feature = "ssr")]
+impl HttpConfig {
+  // the defaults, with AOS_HTTP_CONNECT_TIMEOUT, AOS_HTTP_READ_TIMEOUT and AOS_HTTP_TIMEOUT in seconds and
+  // AOS_HTTP_USER_AGENT taking precedence
+  pub fn from_env() -> Self {
+    let secs = |name: &str| std::env::var(name).ok().and_then(|v| v.trim().parse::<u64>().ok()).map(std::time::Duration::from_secs);
+    let default = Self::default();
+    Self {
+      connect_timeout: secs("AOS_HTTP_CONNECT_TIMEOUT").unwrap_or(default.connect_timeout),
+      read_timeout: secs("AOS_HTTP_READ_TIMEOUT").unwrap_or(default.read_timeout),
+      timeout: secs("AOS_HTTP_TIMEOUT").unwrap_or(default.timeout),
+      user_agent: std::env::var("AOS_HTTP_USER_AGENT").ok().filter(|u| !u.trim().is_empty()).unwrap_or(default.user_agent),
+    }
+  }
+
+  pub fn build(&self) -> LemmyAppResult<reqwest::Client> {
+    reqwest::Client::builder()
+      .brotli(true)
+      .gzip(true)
+      .connect_timeout(self.connect_timeout)
+      .read_timeout(self.read_timeout)
+      .timeout(self.timeout)
+      .user_agent(self.user_agent.clone())
+      .build()
+      .map_err(|e| LemmyAppError { error_type: LemmyAppErrorType::InternalServerError, content: format!("http client: {:#?}", e) })
+  }
+}
+
+#[cfg(feature = "ssr")]
+static SHARED_HTTP: std::sync::OnceLock<reqwest::Client> = std::sync::OnceLock::new();
+
+// sets up the shared client before the first request is made. later calls, and calls after a request has already
+// built the default client, are refused
+#[cfg(feature = "ssr")]
+pub fn configure_http(config: &HttpConfig) -> LemmyAppResult<()> {
+  let client = config.build()?;
+  SHARED_HTTP
+    .set(client)
+    .map_err(|_| LemmyAppError { error_type: LemmyAppErrorType::InternalServerError, content: "http client already configured".into() })
+}
+
+// the shared client, built from the environment if nothing configured it
+#[cfg(feature = "ssr")]
+pub fn shared_http() -> LemmyAppResult<reqwest::Client> {
+  if let Some(client) = SHARED_HTTP.get() {
+    return Ok(client.clone());
+  }
+  let client = HttpConfig::from_env().build()?;
+  Ok(SHARED_HTTP.get_or_init(|| client).clone())
+}
+
+// what is unread in the inbox. v3 counts each kind, v4 only counts notifications as a whole
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UnreadCounts {
+  pub total: i64,
+  pub replies: Option<i64>,
+  pub mentions: Option<i64>,
+  pub private_messages: Option<i64>,
+}
+
 pub trait Fetch {
+  // where requests go and the login they carry
+  fn instance(&self) -> String;
+  fn jwt(&self) -> Option<String>;
+
   async fn make_request<Response, Form>(&self, method: HttpType, path: &str, form: Form) -> LemmyAppResult<Response>
   where
-    Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug,
-    Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store;
+    Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug + Send,
+    Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store + Send;
+
+  // stale-while-revalidate GET: a cached copy is returned straight away and the fresh response is passed to `on_fresh`
+  // once it arrives. without a cached copy, and on the server, this is an ordinary GET
+  async fn make_revalidating_request<Response, Form>(&self, path: &str, form: Form, on_fresh: Callback<Response>) -> LemmyAppResult<Response>
+  where
+    Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug + Send,
+    Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store + Send,
+  {
+    self.make_request(HttpType::Get, path, form).await
+  }
 }
 
 pub trait LemmyApi: Fetch {
@@ -59,15 +177,34 @@ pub trait LemmyApi: Fetch {
   }
 
   async fn get_comments(&self, form: GetComments) -> LemmyAppResult<GetCommentsResponse> {
-    self.make_request(HttpType::Get, "comment/list", form).await
+    let r = self.make_request(HttpType::Get, "comment/list", form).await;
+    record(r, |e, r| e.update_comments(&r.comments))
   }
 
   async fn list_posts(&self, form: GetPosts) -> LemmyAppResult<GetPostsResponse> {
-    self.make_request(HttpType::Get, "post/list", form).await
+    let r = self.make_request(HttpType::Get, "post/list", form).await;
+    record(r, |e, r| e.update_posts(&r.posts))
   }
 
   async fn get_post(&self, form: GetPost) -> LemmyAppResult<GetPostResponse> {
-    self.make_request(HttpType::Get, "post", form).await
+    let r = self.make_request(HttpType::Get, "post", form).await;
+    record(r, |e, r| e.update_post(r.post_view.clone()))
+  }
+
+  async fn list_posts_revalidating(&self, form: GetPosts, on_fresh: Callback<GetPostsResponse>) -> LemmyAppResult<GetPostsResponse> {
+    // what comes back first may be a cached copy, only the fresh response is recorded
+    let on_fresh = recording(on_fresh, |e, r: &GetPostsResponse| e.update_posts(&r.posts));
+    self.make_revalidating_request("post/list", form, on_fresh).await
+  }
+
+  async fn get_post_revalidating(&self, form: GetPost, on_fresh: Callback<GetPostResponse>) -> LemmyAppResult<GetPostResponse> {
+    let on_fresh = recording(on_fresh, |e, r: &GetPostResponse| e.update_post(r.post_view.clone()));
+    self.make_revalidating_request("post", form, on_fresh).await
+  }
+
+  async fn get_comments_revalidating(&self, form: GetComments, on_fresh: Callback<GetCommentsResponse>) -> LemmyAppResult<GetCommentsResponse> {
+    let on_fresh = recording(on_fresh, |e, r: &GetCommentsResponse| e.update_comments(&r.comments));
+    self.make_revalidating_request("comment/list", form, on_fresh).await
   }
 
   async fn get_site(&self) -> LemmyAppResult<GetSiteResponse> {
@@ -94,7 +231,35 @@ pub trait LemmyApi: Fetch {
     //   }
     // };
 
-    self.make_request(HttpType::Get, "site", GetSite { t: now_in_millis }).await
+    // whole seconds, so the calls made together during hydration share one request
+    let form = GetSite { t: now_in_millis / 1000 * 1000 };
+    let instance = self.instance();
+    let mut r = self.make_request(HttpType::Get, "site", form.clone()).await;
+    // an instance seen for the first time is asked over v3, one that only serves v4 is asked again. anything but a
+    // missing route is a real failure and is passed on as it is
+    if let Err(e) = &r
+      && api_missing(e)
+      && known_version(&instance).is_none()
+    {
+      remember_version(&instance, ApiVersion::V4);
+      r = self.make_request(HttpType::Get, "site", form).await;
+      if r.is_err() {
+        forget_version(&instance);
+      }
+    }
+    // only a site that loaded says which version an instance speaks, a failure leaves what is known as it was
+    match r {
+      Ok(mut site) => {
+        let version = ApiVersion::detect(&site);
+        remember_version(&instance, version);
+        // v4 no longer sends the logged in user along with the site
+        if version == ApiVersion::V4 && site.my_user.is_none() && self.jwt().is_some() {
+          site.my_user = self.make_request(HttpType::Get, "account", ()).await.ok();
+        }
+        Ok(site)
+      }
+      Err(e) => Err(e),
+    }
   }
 
   async fn report_post(&self, form: CreatePostReport) -> LemmyAppResult<PostReportResponse> {
@@ -102,31 +267,73 @@ pub trait LemmyApi: Fetch {
   }
 
   async fn block_user(&self, form: BlockPerson) -> LemmyAppResult<BlockPersonResponse> {
-    self.make_request(HttpType::Post, "user/block", form).await
+    let person_id = form.person_id;
+    let r = self.make_request(HttpType::Post, "user/block", form).await;
+    invalidate_on_success(r, |c| c.invalidate_person(person_id))
   }
 
   async fn save_post(&self, form: SavePost) -> LemmyAppResult<PostResponse> {
-    self.make_request(HttpType::Put, "post/save", form).await
+    let r = self.make_request(HttpType::Put, "post/save", form.clone()).await;
+    let r = invalidate_on_success(r, |c| c.invalidate_post(form.post_id));
+    let r = record(r, |e, r| e.update_post(r.post_view.clone()));
+    queue_when_offline(r, PendingAction::SavePost(form)).await
   }
 
   async fn like_post(&self, form: CreatePostLike) -> LemmyAppResult<PostResponse> {
-    self.make_request(HttpType::Post, "post/like", form).await
+    let r = self.make_request(HttpType::Post, "post/like", form.clone()).await;
+    let r = invalidate_on_success(r, |c| c.invalidate_post(form.post_id));
+    let r = record(r, |e, r| e.update_post(r.post_view.clone()));
+    queue_when_offline(r, PendingAction::LikePost(form)).await
   }
 
   async fn like_comment(&self, form: CreateCommentLike) -> LemmyAppResult<CommentResponse> {
-    self.make_request(HttpType::Post, "comment/like", form).await
+    let r = self.make_request(HttpType::Post, "comment/like", form.clone()).await;
+    let r = invalidate_on_success(r, |c| c.invalidate_comment(form.comment_id));
+    let r = record(r, |e, r| e.update_comment(r.comment_view.clone()));
+    queue_when_offline(r, PendingAction::LikeComment(form)).await
   }
 
   async fn save_comment(&self, form: SaveComment) -> LemmyAppResult<CommentResponse> {
-    self.make_request(HttpType::Put, "comment/save", form).await
+    let r = self.make_request(HttpType::Put, "comment/save", form.clone()).await;
+    let r = invalidate_on_success(r, |c| c.invalidate_comment(form.comment_id));
+    let r = record(r, |e, r| e.update_comment(r.comment_view.clone()));
+    queue_when_offline(r, PendingAction::SaveComment(form)).await
   }
 
-  async fn unread_count(&self) -> LemmyAppResult<GetUnreadCountResponse> {
-    self.make_request(HttpType::Get, "user/unread_count", ()).await
+  async fn unread_count(&self) -> LemmyAppResult<UnreadCounts> {
+    let mut r: UnreadCounts = self.make_request(HttpType::Get, "user/unread_count", ()).await?;
+    if let (Some(replies), Some(mentions), Some(private_messages)) = (r.replies, r.mentions, r.private_messages) {
+      r.total = replies + mentions + private_messages;
+    }
+    Ok(r)
   }
 
   async fn get_user(&self, form: GetPersonDetails) -> LemmyAppResult<GetPersonDetailsResponse> {
-    self.make_request(HttpType::Get, "user", form).await
+    let mut r = self.make_request(HttpType::Get, "user", form.clone()).await;
+    // v4 serves what a person wrote from a combined list of its own
+    if let Ok(details) = &mut r
+      && api_version(&self.instance()) == ApiVersion::V4
+      && let Ok(content) = self.make_request::<PersonContent, _>(HttpType::Get, "person/content", form).await
+    {
+      details.posts = content.posts;
+      details.comments = content.comments;
+    }
+    record(r, |e, r| {
+      e.update_posts(&r.posts);
+      e.update_comments(&r.comments);
+    })
+  }
+
+  async fn get_user_revalidating(
+    &self,
+    form: GetPersonDetails,
+    on_fresh: Callback<GetPersonDetailsResponse>,
+  ) -> LemmyAppResult<GetPersonDetailsResponse> {
+    let on_fresh = recording(on_fresh, |e, r: &GetPersonDetailsResponse| {
+      e.update_posts(&r.posts);
+      e.update_comments(&r.comments);
+    });
+    self.make_revalidating_request("user", form, on_fresh).await
   }
 
   async fn replies_user(&self, form: GetReplies) -> LemmyAppResult<GetRepliesResponse> {
@@ -146,15 +353,25 @@ pub trait LemmyApi: Fetch {
   }
 
   async fn reply_comment(&self, form: CreateComment) -> LemmyAppResult<CommentResponse> {
-    self.make_request(HttpType::Post, "comment", form).await
+    let r = self.make_request(HttpType::Post, "comment", form.clone()).await;
+    let r = invalidate_on_success(r, |c| c.invalidate_post_comments(form.post_id));
+    let r = record(r, |e, r| e.update_comment(r.comment_view.clone()));
+    queue_when_offline(r, PendingAction::ReplyComment(form)).await
   }
 
   async fn edit_comment(&self, form: EditComment) -> LemmyAppResult<CommentResponse> {
-    self.make_request(HttpType::Put, "comment", form).await
+    let r = self.make_request(HttpType::Put, "comment", form.clone()).await;
+    let r = invalidate_on_success(r, |c| c.invalidate_comment(form.comment_id));
+    let r = record(r, |e, r| e.update_comment(r.comment_view.clone()));
+    queue_when_offline(r, PendingAction::EditComment(form)).await
   }
 
   async fn search(&self, form: Search) -> LemmyAppResult<SearchResponse> {
-    self.make_request(HttpType::Get, "search", form).await
+    let r = self.make_request(HttpType::Get, "search", form).await;
+    record(r, |e, r| {
+      e.update_posts(&r.posts);
+      e.update_comments(&r.comments);
+    })
   }
 
   async fn get_comment(&self, form: GetComment) -> LemmyAppResult<CommentResponse> {
@@ -167,22 +384,194 @@ pub trait LemmyApi: Fetch {
 }
 
 impl LemmyApi for LemmyClient {}
+impl LemmyApi for InstanceClient {}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PersonContent {
+  posts: Vec<PostView>,
+  comments: Vec<CommentView>,
+}
 
-fn build_route(route: &str) -> String {
+fn current_instance() -> String {
   let ReadInstanceCookie(get_instance_cookie) = expect_context::<ReadInstanceCookie>();
-  let WriteInstanceCookie(set_instance_cookie) = expect_context::<WriteInstanceCookie>();
-  if let Some(t) = get_instance_cookie.get() {
-    set_instance_cookie.set(Some(t));
-  } else {
-    set_instance_cookie.set(Some("lemmy.world".to_owned()));
+  visitor_instance(get_instance_cookie.get_untracked())
+}
+
+fn current_jwt() -> Option<String> {
+  let ReadAuthCookie(get_auth_cookie) = expect_context::<ReadAuthCookie>();
+  get_auth_cookie.get_untracked()
+}
+
+fn route_for(instance: &str, route: &str) -> String {
+  format!("https://{}/{}/{}", instance, api_version(instance).prefix(), route)
+}
+
+impl LemmyClient {
+  // the instance and login in the cookies right now, as a client that no longer needs the context. the instance
+  // cookie is written back so it stays alive while it is in use
+  pub fn connection(&self) -> InstanceClient {
+    let instance = current_instance();
+    let WriteInstanceCookie(set_instance_cookie) = expect_context::<WriteInstanceCookie>();
+    set_instance_cookie.set(Some(instance.clone()));
+    InstanceClient::new(instance, current_jwt())
   }
-  format!("https://{}/api/v3/{}", get_instance_cookie.get().unwrap_or("".to_owned()), route)
+}
+
+impl InstanceClient {
+  pub fn new(instance: impl Into<String>, jwt: Option<String>) -> Self {
+    Self {
+      instance: instance.into(),
+      jwt,
+      #[cfg(feature = "ssr")]
+      http: None,
+    }
+  }
+
+  // a client of its own instead of the shared one, e.g. with other timeouts
+  #[cfg(feature = "ssr")]
+  pub fn with_http(mut self, http: reqwest::Client) -> Self {
+    self.http = Some(http);
+    self
+  }
+
+  fn route(&self, route: &str) -> String {
+    route_for(&self.instance, route)
+  }
+}
+
+// a login the instance turned down is dropped, so the app carries on anonymously
+fn signed_out_on_incorrect_login<T>(result: LemmyAppResult<T>) -> LemmyAppResult<T> {
+  if let Err(LemmyAppError { error_type: LemmyAppErrorType::ApiError(LemmyErrorType::IncorrectLogin), .. }) = &result
+    && let Some(WriteAuthCookie(set_auth_cookie)) = use_context::<WriteAuthCookie>()
+  {
+    set_auth_cookie.set(None);
+  }
+  result
+}
+
+// failed requests are logged by the app's client, an instance client leaves its output to whoever uses it
+fn logged<T>(result: LemmyAppResult<T>) -> LemmyAppResult<T> {
+  if let Err(e) = &result {
+    log!("{:#?}", e);
+  }
+  result
+}
+
+// v3 forms and responses go out and come back untouched, any other version is translated by its backend on the way
+async fn through_backend<Response, Form, Fut>(
+  version: ApiVersion,
+  path: &str,
+  form: Form,
+  fetch: impl FnOnce(String, BackendForm) -> Fut,
+) -> LemmyAppResult<Response>
+where
+  Response: DeserializeOwned,
+  Form: Serialize + Store,
+  Fut: Future<Output = LemmyAppResult<serde_json::Value>>,
+{
+  let backend = backend(version);
+  let form = BackendForm { store: form.store_name(), form: backend.form(path, serde_json::to_value(&form)?) };
+  let response = fetch(backend.path(path), form).await?;
+  serde_json::from_value(backend.response(path, response)).map_err(Into::into)
+}
+
+// what an instance answers on a route of an api version it does not serve
+fn api_missing(error: &LemmyAppError) -> bool {
+  matches!(error.error_type, LemmyAppErrorType::NotFound | LemmyAppErrorType::ApiError(LemmyErrorType::NotFound))
+}
+
+// idempotent GETs are retried on transport errors, rate limiting and gateway errors
+const RETRY_ATTEMPTS: u32 = 3;
+const RETRY_BASE_MILLIS: u64 = 250;
+// a longer Retry-After than this is surfaced to the user rather than waited out
+const RETRY_MAX_MILLIS: u64 = 10_000;
+
+fn retryable_status(status: u16) -> bool {
+  matches!(status, 429 | 502 | 503 | 504)
+}
+
+// only the delta-seconds form, an http date falls back to backoff
+fn parse_retry_after(value: &str) -> Option<u64> {
+  value.trim().parse::<u64>().ok().map(|s| s * 1000)
+}
+
+// exponential backoff with jitter, unless the server said how long to wait
+fn retry_delay(attempt: u32, retry_after: Option<u64>, random: f64) -> u64 {
+  retry_after.unwrap_or_else(|| (RETRY_BASE_MILLIS.saturating_mul(1 << attempt.min(16)) as f64 * (0.5 + random / 2.0)) as u64)
+}
+
+fn rate_limited(wait_millis: u64) -> LemmyAppError {
+  let until = jiff::Timestamp::now().as_millisecond() + wait_millis as i64;
+  LemmyAppError { error_type: LemmyAppErrorType::RateLimited(until), content: format!("retry after {}ms", wait_millis) }
+}
+
+fn in_flight_key<Form: Serialize>(route: &str, form: &Form, jwt: Option<&str>) -> String {
+  format!("{}?{} {}", route, serde_urlencoded::to_string(form).unwrap_or_default(), jwt.unwrap_or_default())
+}
+
+// identical GETs issued while one is still in flight wait on that one instead of going to the network. the response
+// is shared as json so callers asking for the same route need not agree on a type that is Clone
+#[cfg(feature = "ssr")]
+async fn coalesce<Response>(key: String, request: impl Future<Output = LemmyAppResult<Response>> + Send + 'static) -> LemmyAppResult<Response>
+where
+  Response: Serialize + DeserializeOwned,
+{
+  use futures::future::{BoxFuture, FutureExt, Shared};
+  use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+  };
+
+  static IN_FLIGHT: LazyLock<Mutex<HashMap<String, Shared<BoxFuture<'static, LemmyAppResult<String>>>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+  let shared = IN_FLIGHT
+    .lock()
+    .unwrap_or_else(|e| e.into_inner())
+    .entry(key.clone())
+    .or_insert_with(|| async move { request.await.and_then(|r| serde_json::to_string(&r).map_err(Into::into)) }.boxed().shared())
+    .clone();
+  let result = shared.clone().await;
+  let mut in_flight = IN_FLIGHT.lock().unwrap_or_else(|e| e.into_inner());
+  if in_flight.get(&key).is_some_and(|s| s.ptr_eq(&shared)) {
+    in_flight.remove(&key);
+  }
+  drop(in_flight);
+  result.and_then(|s| serde_json::from_str::<Response>(&s).map_err(Into::into))
+}
+
+#[cfg(not(feature = "ssr"))]
+async fn coalesce<Response>(key: String, request: impl Future<Output = LemmyAppResult<Response>> + 'static) -> LemmyAppResult<Response>
+where
+  Response: Serialize + DeserializeOwned,
+{
+  use futures::future::{FutureExt, LocalBoxFuture, Shared};
+  use std::{cell::RefCell, collections::HashMap};
+
+  thread_local! {
+    static IN_FLIGHT: RefCell<HashMap<String, Shared<LocalBoxFuture<'static, LemmyAppResult<String>>>>> = RefCell::new(HashMap::new());
+  }
+
+  let shared = IN_FLIGHT.with_borrow_mut(|in_flight| {
+    in_flight
+      .entry(key.clone())
+      .or_insert_with(|| async move { request.await.and_then(|r| serde_json::to_string(&r).map_err(Into::into)) }.boxed_local().shared())
+      .clone()
+  });
+  let result = shared.clone().await;
+  IN_FLIGHT.with_borrow_mut(|in_flight| {
+    if in_flight.get(&key).is_some_and(|s| s.ptr_eq(&shared)) {
+      in_flight.remove(&key);
+    }
+  });
+  result.and_then(|s| serde_json::from_str::<Response>(&s).map_err(Into::into))
 }
 
 #[cfg(feature = "ssr")]
 mod client {
 
   use super::*;
+  use crate::response_cache;
 
   trait MaybeBearerAuth {
     fn maybe_bearer_auth(self, token: Option<impl core::fmt::Display>) -> reqwest::RequestBuilder;
@@ -204,29 +593,56 @@ mod client {
     }
   }
 
-  impl Fetch for LemmyClient {
-    async fn make_request<Response, Form>(&self, method: HttpType, path: &str, form: Form) -> LemmyAppResult<Response>
+  // no rand dependency on the server, a freshly keyed hasher is random enough for jitter
+  fn random() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut h = std::collections::hash_map::RandomState::new().build_hasher();
+    h.write_u64(0);
+    (h.finish() % 1000) as f64 / 1000.0
+  }
+
+  impl InstanceClient {
+    async fn send_request<Response, Form>(&self, method: HttpType, path: &str, form: Form) -> LemmyAppResult<Response>
     where
-      Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug,
-      Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store,
+      Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug + Send,
+      Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store + Send,
     {
-      let WriteAuthCookie(set_auth_cookie) = expect_context::<WriteAuthCookie>();
-      let ReadAuthCookie(get_auth_cookie) = expect_context::<ReadAuthCookie>();
-      let jwt = get_auth_cookie.get();
-      let route = build_route(path);
-
-      log!("{}", format!("{}?{}", route, serde_urlencoded::to_string(&form).unwrap_or("".to_owned())));
+      let jwt = self.jwt.clone();
+      let route = self.route(path);
+      let client = match &self.http {
+        Some(http) => http.clone(),
+        None => shared_http()?,
+      };
 
-      let client = reqwest::Client::builder().brotli(true).build().unwrap();
-
-      let m = match method {
-        HttpType::Get => client.get(&route).maybe_bearer_auth(jwt.clone()).query(&form).send(),
-        HttpType::Post => client.post(&route).maybe_bearer_auth(jwt.clone()).form(&form).send(),
-        HttpType::Put => client.put(&route).maybe_bearer_auth(jwt.clone()).form(&form).send(),
-      }
-      .await;
+      let mut attempt = 0u32;
+      let (m, wait) = loop {
+        let m = match method {
+          HttpType::Get => client.get(&route).maybe_bearer_auth(jwt.clone()).query(&form).send(),
+          HttpType::Post => client.post(&route).maybe_bearer_auth(jwt.clone()).form(&form).send(),
+          HttpType::Put => client.put(&route).maybe_bearer_auth(jwt.clone()).form(&form).send(),
+        }
+        .await;
+        let retry_after = match &m {
+          Ok(r) if retryable_status(r.status().as_u16()) => {
+            r.headers().get(reqwest::header::RETRY_AFTER).and_then(|v| v.to_str().ok()).and_then(parse_retry_after)
+          }
+          Ok(_) => break (m, 0),
+          // an instance that has already used up the timeout once is not given it again
+          Err(e) if e.is_timeout() => break (m, 0),
+          Err(_) => None,
+        };
+        let wait = retry_delay(attempt, retry_after, random());
+        if method != HttpType::Get || attempt >= RETRY_ATTEMPTS || wait > RETRY_MAX_MILLIS {
+          break (m, wait);
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(wait)).await;
+        attempt += 1;
+      };
 
       match m {
+        Err(re) if re.is_timeout() || re.is_connect() => {
+          return Err(LemmyAppError { error_type: LemmyAppErrorType::UnreachableInstance, content: self.instance.clone() });
+        }
         Err(re) => {
           return Err(LemmyAppError {
             error_type: LemmyAppErrorType::ApiError(LemmyErrorType::Unknown("reqwest error".into())),
@@ -235,25 +651,18 @@ mod client {
         }
         Ok(r) => {
           match r.status().as_u16() {
-            400..=599 => {
+            429 => return Err(rate_limited(wait)),
+            status @ 400..=599 => {
               let api_result = r.json::<LemmyErrorType>().await;
 
               match api_result {
-                Ok(LemmyErrorType::IncorrectLogin) => {
-                  log!("{:#?}", LemmyErrorType::IncorrectLogin);
-                  set_auth_cookie.set(None);
-                  return Err(LemmyAppError {
-                    error_type: LemmyAppErrorType::ApiError(LemmyErrorType::IncorrectLogin),
-                    content: format!("{:#?}", LemmyErrorType::IncorrectLogin),
-                  });
-                }
                 Ok(le) => {
-                  log!("{:#?}", le);
                   return Err(LemmyAppError { error_type: LemmyAppErrorType::ApiError(le.clone()), content: format!("{:#?}", le) });
                 }
                 Err(e) => {
-                  log!("{:#?}", e);
-                  return Err(LemmyAppError { error_type: LemmyAppErrorType::Unknown, content: format!("{:#?}", e) });
+                  // a route the instance does not serve at all answers without a lemmy error
+                  let error_type = if status == 404 { LemmyAppErrorType::NotFound } else { LemmyAppErrorType::Unknown };
+                  return Err(LemmyAppError { error_type, content: format!("{:#?}", e) });
                 }
               }
             }
@@ -274,6 +683,85 @@ mod client {
         }
       }
     }
+
+    async fn fetch<Response, Form>(&self, method: HttpType, path: &str, form: Form) -> LemmyAppResult<Response>
+    where
+      Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug + Send,
+      Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store + Send,
+    {
+      if method != HttpType::Get {
+        return self.send_request(method, path, form).await;
+      }
+      let key = in_flight_key(&self.route(path), &form, self.jwt.as_deref());
+      let (client, path) = (self.clone(), path.to_owned());
+      coalesce(key, async move { client.send_request(method, &path, form).await }).await
+    }
+  }
+
+  impl Fetch for InstanceClient {
+    fn instance(&self) -> String {
+      self.instance.clone()
+    }
+
+    fn jwt(&self) -> Option<String> {
+      self.jwt.clone()
+    }
+
+    async fn make_request<Response, Form>(&self, method: HttpType, path: &str, form: Form) -> LemmyAppResult<Response>
+    where
+      Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug + Send,
+      Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store + Send,
+    {
+      let version = api_version(&self.instance);
+      if version == ApiVersion::V3 {
+        return self.fetch(method, path, form).await;
+      }
+      let client = self.clone();
+      through_backend(version, path, form, |path, form| async move { client.fetch(method, &path, form).await }).await
+    }
+  }
+
+  impl Fetch for LemmyClient {
+    fn instance(&self) -> String {
+      current_instance()
+    }
+
+    fn jwt(&self) -> Option<String> {
+      current_jwt()
+    }
+
+    async fn make_request<Response, Form>(&self, method: HttpType, path: &str, form: Form) -> LemmyAppResult<Response>
+    where
+      Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug + Send,
+      Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store + Send,
+    {
+      if let Some(fixtures) = fixtures() {
+        return fixtures.make_request(method, path, form).await;
+      }
+      // logged here rather than by the instance client, whose other users may want a quiet stdout
+      let client = self.connection();
+      log!("{}", format!("{}?{}", client.route(path), serde_urlencoded::to_string(&form).unwrap_or("".to_owned())));
+      // logged out visitors all see the same responses, so theirs are shared for a while. asking for a key that is
+      // already on its way waits for that answer instead of sending another
+      if method == HttpType::Get && client.jwt.is_none() && response_cache::enabled() {
+        let key = response_cache::key(&client.instance, path, &form);
+        if let Some(cached) = response_cache::get::<Response>(&key) {
+          return Ok(cached);
+        }
+        let path = path.to_owned();
+        return logged(
+          coalesce(key.clone(), async move {
+            let r = client.make_request::<Response, _>(method, &path, form).await;
+            if let Ok(response) = &r {
+              response_cache::put(key, response);
+            }
+            r
+          })
+          .await,
+        );
+      }
+      signed_out_on_incorrect_login(logged(client.make_request(method, path, form).await))
+    }
   }
 }
 
@@ -283,7 +771,7 @@ mod client {
   use super::*;
   use crate::OnlineSetter;
   use gloo_net::{http, http::RequestBuilder};
-  use leptos::wasm_bindgen::UnwrapThrowExt;
+  use leptos::{task::spawn_local_scoped_with_cancellation, wasm_bindgen::UnwrapThrowExt};
 
   trait MaybeBearerAuth {
     fn maybe_bearer_auth(self, token: Option<&str>) -> Self;
@@ -295,32 +783,67 @@ mod client {
     }
   }
 
-  impl Fetch for LemmyClient {
-    async fn make_request<Response, Form>(&self, method: HttpType, path: &str, form: Form) -> LemmyAppResult<Response>
-    where
-      Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug,
-      Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store,
-    {
-      let route = &build_route(path);
+  // what responses are stored under: the form, and the instance and login it was answered for. the login is kept
+  // as a hash, the token itself stays in its cookie
+  #[derive(Serialize)]
+  struct ResponseKey<'a, Form> {
+    instance: String,
+    login: Option<u64>,
+    form: &'a Form,
+  }
+
+  impl<'a, Form: Store> ResponseKey<'a, Form> {
+    fn new(client: &InstanceClient, form: &'a Form) -> Self {
+      use std::hash::{DefaultHasher, Hash, Hasher};
+      let login = client.jwt.as_ref().map(|jwt| {
+        let mut hasher = DefaultHasher::new();
+        jwt.hash(&mut hasher);
+        hasher.finish()
+      });
+      Self { instance: client.instance.clone(), login, form }
+    }
+  }
 
-      let WriteAuthCookie(set_auth_cookie) = expect_context::<WriteAuthCookie>();
-      let ReadAuthCookie(get_auth_cookie) = expect_context::<ReadAuthCookie>();
-      let jwt = get_auth_cookie.get();
+  impl<Form: Store> Store for ResponseKey<'_, Form> {
+    fn store_name(&self) -> &'static str {
+      self.form.store_name()
+    }
+  }
 
-      let online = expect_context::<RwSignal<OnlineSetter>>();
+  async fn sleep(millis: u64) {
+    let promise = web_sys::js_sys::Promise::new(&mut |resolve, _| {
+      let _ = window().set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis as i32);
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+  }
+
+  impl InstanceClient {
+    // an abortable request is cancelled along with the owner that sent it, which only makes sense when no one else
+    // is waiting on it
+    async fn send_request<Response, Form>(&self, method: HttpType, path: &str, form: Form, abortable: bool) -> LemmyAppResult<Response>
+    where
+      Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug + Send,
+      Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store + Send,
+    {
+      let route = &self.route(path);
+      let query = &self.fetch_query(path, form.clone());
+      let jwt = self.jwt.clone();
 
       let s = SendWrapper::new(async move {
-        let abort_controller = SendWrapper::new(web_sys::AbortController::new().ok());
+        let abort_controller = SendWrapper::new(if abortable { web_sys::AbortController::new().ok() } else { None });
         let abort_signal = abort_controller.as_ref().map(|a| a.signal());
-        on_cleanup(move || {
-          if let Some(abort_controller) = abort_controller.take() {
-            abort_controller.abort()
-          }
-        });
+        if abortable {
+          on_cleanup(move || {
+            if let Some(abort_controller) = abort_controller.take() {
+              abort_controller.abort()
+            }
+          });
+        }
 
-        if online.get().0 {
+        let mut attempt = 0u32;
+        let (r, wait) = loop {
           let r = match method {
-            HttpType::Get => http::Request::get(&build_fetch_query(path, form.clone()))
+            HttpType::Get => http::Request::get(query)
               .cache(web_sys::RequestCache::Default)
               .maybe_bearer_auth(jwt.as_deref())
               .abort_signal(abort_signal.as_ref())
@@ -338,70 +861,164 @@ mod client {
               .expect_throw("Could not parse json form"),
           }
           .send()
-          .await?;
-
-          match r.status() {
-            400..=599 => {
-              let api_result = r.json::<LemmyErrorType>().await;
-              match api_result {
-                Ok(LemmyErrorType::IncorrectLogin) => {
-                  log!("{:#?}", LemmyErrorType::IncorrectLogin);
-                  set_auth_cookie.set(None);
-                  return Err(LemmyAppError {
-                    error_type: LemmyAppErrorType::ApiError(LemmyErrorType::IncorrectLogin),
-                    content: format!("{:#?}", LemmyErrorType::IncorrectLogin),
-                  });
-                }
-                Ok(le) => {
-                  log!("{:#?}", le);
-                  return Err(LemmyAppError { error_type: LemmyAppErrorType::ApiError(le.clone()), content: format!("{:#?}", le) });
-                }
-                Err(e) => {
-                  log!("{:#?}", e);
-                  return Err(LemmyAppError { error_type: LemmyAppErrorType::Unknown, content: format!("{:#?}", e) });
-                }
-              }
-            }
-            _ => {}
+          .await;
+          let retry_after = match &r {
+            // Retry-After is only readable when the instance exposes it to cross origin requests
+            Ok(r) if retryable_status(r.status()) => r.headers().get("Retry-After").and_then(|v| parse_retry_after(&v)),
+            Ok(_) => break (r, 0),
+            Err(_) => None,
           };
+          let wait = retry_delay(attempt, retry_after, web_sys::js_sys::Math::random());
+          if method != HttpType::Get || attempt >= RETRY_ATTEMPTS || wait > RETRY_MAX_MILLIS || abort_signal.as_ref().is_some_and(|a| a.aborted()) {
+            break (r, wait);
+          }
+          sleep(wait).await;
+          attempt += 1;
+        };
+        let r = r?;
 
-          let t = r.text().await?;
-
-          if t.is_empty() {
-            serde_json::from_str::<Response>("{}").map_err(Into::into)
-          } else {
-            let o = serde_json::from_str::<Response>(&t).map_err(Into::into);
-            if method == HttpType::Get {
-              if let Ok(ref e) = o {
-                if let Ok(d) = IndexedDb::new().await {
-                  if let Ok(_c) = d.set(&form, &e).await {}
-                }
+        match r.status() {
+          429 => return Err(rate_limited(wait)),
+          status @ 400..=599 => {
+            let api_result = r.json::<LemmyErrorType>().await;
+            match api_result {
+              Ok(le) => {
+                return Err(LemmyAppError { error_type: LemmyAppErrorType::ApiError(le.clone()), content: format!("{:#?}", le) });
               }
-            }
-            o
-          }
-        } else {
-          if method == HttpType::Get {
-            if let Ok(d) = IndexedDb::new().await {
-              if let Ok(c) = d.get(&form).await {
-                if let Some(o) = c {
-                  return Ok(o);
-                }
+              Err(e) => {
+                let error_type = if status == 404 { LemmyAppErrorType::NotFound } else { LemmyAppErrorType::Unknown };
+                return Err(LemmyAppError { error_type, content: format!("{:#?}", e) });
               }
             }
           }
-          let e = LemmyAppError { error_type: LemmyAppErrorType::OfflineError, content: String::from("") };
-          Err(e)
+          _ => {}
+        };
+
+        let t = r.text().await?;
+
+        if t.is_empty() {
+          serde_json::from_str::<Response>("{}").map_err(Into::into)
+        } else {
+          serde_json::from_str::<Response>(&t).map_err(Into::into)
         }
       })
       .await;
 
       s
     }
+
+    async fn fetch<Response, Form>(&self, method: HttpType, path: &str, form: Form) -> LemmyAppResult<Response>
+    where
+      Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug + Send,
+      Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store + Send,
+    {
+      if method != HttpType::Get {
+        return self.send_request(method, path, form, true).await;
+      }
+      // the shared request runs in whichever owner polled it first, so it has to outlive that owner for the others
+      let key = in_flight_key(&self.route(path), &form, self.jwt.as_deref());
+      let (client, path) = (self.clone(), path.to_owned());
+      coalesce(key, async move { client.send_request(method, &path, form, false).await }).await
+    }
+
+    fn fetch_query<T: Serialize>(&self, path: &str, form: T) -> String {
+      let form_str = serde_urlencoded::to_string(&form).unwrap_or("".to_owned());
+      format!("{}?{}", self.route(path), form_str)
+    }
   }
 
-  fn build_fetch_query<T: Serialize>(path: &str, form: T) -> String {
-    let form_str = serde_urlencoded::to_string(&form).unwrap_or("".to_owned());
-    format!("{}?{}", build_route(path), form_str)
+  impl Fetch for InstanceClient {
+    fn instance(&self) -> String {
+      self.instance.clone()
+    }
+
+    fn jwt(&self) -> Option<String> {
+      self.jwt.clone()
+    }
+
+    async fn make_request<Response, Form>(&self, method: HttpType, path: &str, form: Form) -> LemmyAppResult<Response>
+    where
+      Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug + Send,
+      Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store + Send,
+    {
+      let version = api_version(&self.instance);
+      if version == ApiVersion::V3 {
+        return self.fetch(method, path, form).await;
+      }
+      let client = self.clone();
+      through_backend(version, path, form, |path, form| async move { client.fetch(method, &path, form).await }).await
+    }
+  }
+
+  impl Fetch for LemmyClient {
+    fn instance(&self) -> String {
+      current_instance()
+    }
+
+    fn jwt(&self) -> Option<String> {
+      current_jwt()
+    }
+
+    // offline the app answers GETs from what it stored the last time they succeeded
+    async fn make_request<Response, Form>(&self, method: HttpType, path: &str, form: Form) -> LemmyAppResult<Response>
+    where
+      Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug + Send,
+      Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store + Send,
+    {
+      if let Some(fixtures) = fixtures() {
+        return fixtures.make_request(method, path, form).await;
+      }
+      let online = use_context::<RwSignal<OnlineSetter>>().map(|o| o.get_untracked().0).unwrap_or(true);
+      let client = self.connection();
+      let path = path.to_owned();
+      let r = SendWrapper::new(async move {
+        if !online {
+          if method == HttpType::Get
+            && let Ok(d) = IndexedDb::shared().await
+            && let Ok(Some(o)) = d.get(&ResponseKey::new(&client, &form)).await
+          {
+            return Ok(o);
+          }
+          return Err(LemmyAppError { error_type: LemmyAppErrorType::OfflineError, content: String::from("") });
+        }
+        let r = client.make_request(method.clone(), &path, form.clone()).await;
+        if method == HttpType::Get
+          && let Ok(o) = &r
+          && let Ok(d) = IndexedDb::shared().await
+        {
+          let _ = d.set(&ResponseKey::new(&client, &form), o).await;
+        }
+        r
+      })
+      .await;
+      signed_out_on_incorrect_login(logged(r))
+    }
+
+    async fn make_revalidating_request<Response, Form>(&self, path: &str, form: Form, on_fresh: Callback<Response>) -> LemmyAppResult<Response>
+    where
+      Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug + Send,
+      Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store + Send,
+    {
+      let online = expect_context::<RwSignal<OnlineSetter>>();
+      let client = self.connection();
+      let path = path.to_owned();
+      // offline reads already come from the cache in make_request. only what was stored for this instance and login
+      // is shown while the fresh copy loads
+      SendWrapper::new(async move {
+        if online.get_untracked().0
+          && let Ok(d) = IndexedDb::shared().await
+          && let Ok(Some(cached)) = d.get::<_, Response>(&ResponseKey::new(&client, &form)).await
+        {
+          spawn_local_scoped_with_cancellation(async move {
+            if let Ok(fresh) = LemmyClient.make_request::<Response, Form>(HttpType::Get, &path, form).await {
+              on_fresh.run(fresh);
+            }
+          });
+          return Ok(cached);
+        }
+        LemmyClient.make_request(HttpType::Get, &path, form).await
+      })
+      .await
+    }
   }
 }
@@ -1,9 +1,14 @@
 use crate::{
-  OnlineSetter,
   client::*,
   db::csr_indexed_db::*,
+  entities::Entities,
+  editor::MarkdownEditor,
+  emoji::*,
   errors::LemmyAppError,
   icon::{Icon, IconType::*},
+  markdown::*,
+  mention::participants,
+  pending::is_queued,
 };
 use lemmy_api_common::{
   comment::{CreateComment, CreateCommentLike, EditComment, GetComment, SaveComment},
@@ -11,14 +16,20 @@ use lemmy_api_common::{
   lemmy_db_views::structs::{CommentView, LocalUserView},
   site::{GetModlog, GetSiteResponse, MyUserInfo},
 };
-use leptos::{html::Textarea, logging::log, prelude::*, task::*, *};
+use leptos::{
+  html::{Div, Textarea},
+  logging::log,
+  prelude::*,
+  task::*,
+  *,
+};
 use leptos_dom::helpers::TimeoutHandle;
 use leptos_router::{
   components::{A, Form},
-  hooks::use_navigate,
+  hooks::{use_navigate, use_query_map},
 };
 use leptos_use::{UseIntersectionObserverOptions, use_intersection_observer_with_options};
-use web_sys::{DragEvent, HtmlAnchorElement, HtmlImageElement, MouseEvent, PointerEvent, TouchEvent, WheelEvent, wasm_bindgen::JsCast};
+use web_sys::{DragEvent, HtmlAnchorElement, HtmlImageElement, MouseEvent, PointerEvent, TouchEvent, wasm_bindgen::JsCast};
 
 #[component]
 pub fn Comment(
@@ -31,9 +42,9 @@ pub fn Comment(
   highlight_user_id: RwSignal<Option<PersonId>>,
   post_id: Signal<Option<i32>>,
   selected_drag_offset: RwSignal<(usize, f64, i32)>,
+  #[prop(optional)] highlight_comment_id: Signal<Option<i32>>,
 ) -> impl IntoView {
   let ssr_site = expect_context::<Resource<Result<GetSiteResponse, LemmyAppError>>>();
-  let online = expect_context::<RwSignal<OnlineSetter>>();
 
   let on_toggle = move |i: i32| {
     if hidden_comments.get().contains(&i) {
@@ -44,7 +55,7 @@ pub fn Comment(
     #[cfg(not(feature = "ssr"))]
     spawn_local_scoped_with_cancellation(async move {
       if let Some(p) = post_id.get() {
-        if let Ok(d) = IndexedDb::new().await {
+        if let Ok(d) = IndexedDb::shared().await {
           if let Ok(_) = d.set(&p, &hidden_comments.get()).await {}
         }
       }
@@ -78,35 +89,19 @@ pub fn Comment(
   //     || descendants.get().iter().find(|ct| ct.comment.id.0 == selected_drag_offset.get().2).is_some()
   // });
 
-  let comment_view = RwSignal::new(comment.get());
+  let comment_view = expect_context::<Entities>().comment(comment.get());
   let comment_copy = RwSignal::new(comment.get());
+  let thread_participants = Signal::derive(move || {
+    let mut thread = comments.get();
+    thread.push(comment_view.get());
+    participants(&thread)
+  });
+
+  let draft_context = Signal::derive(move || comment_copy.get().comment.content.chars().take(200).collect::<String>());
 
   let safe_html = Signal::derive(move || {
     let content = comment_view.get().comment.content;
-    let mut safe_html = String::new();
-
-    let mut options = pulldown_cmark::Options::empty();
-    options.insert(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
-    options.insert(pulldown_cmark::Options::ENABLE_TABLES);
-    options.insert(pulldown_cmark::Options::ENABLE_SUPERSCRIPT);
-    options.insert(pulldown_cmark::Options::ENABLE_SUBSCRIPT);
-    options.insert(pulldown_cmark::Options::ENABLE_CONTAINER_EXTENSIONS);
-    options.insert(pulldown_cmark::Options::ENABLE_LINKIFY_LEMMY);
-    options.insert(pulldown_cmark::Options::ENABLE_LINKIFY_HTTP);
-    let parser = pulldown_cmark::Parser::new_ext(&content, options);
-
-    let custom = parser.map(|event| match event {
-      pulldown_cmark::Event::Html(text) => {
-        let er = format!("<p>{}</p>", html_escape::encode_safe(&text).to_string());
-        pulldown_cmark::Event::Html(er.into())
-      }
-      pulldown_cmark::Event::InlineHtml(text) => {
-        let er = html_escape::encode_safe(&text).to_string();
-        pulldown_cmark::Event::InlineHtml(er.into())
-      }
-      _ => event,
-    });
-    pulldown_cmark::html::push_html(&mut safe_html, custom);
+    let safe_html = markdown_to_html(&content, Profile::Body, custom_emojis(ssr_site));
 
     safe_html
   });
@@ -150,6 +145,9 @@ pub fn Comment(
         Ok(o) => {
           comment_view.set(o.comment_view);
         }
+        Err(_) if is_queued(&result) => {
+          comment_view.update(|cv| cv.my_vote = Some(score));
+        }
         Err(_e) => {}
       }
     });
@@ -174,6 +172,9 @@ pub fn Comment(
         Ok(o) => {
           comment_view.set(o.comment_view);
         }
+        Err(_) if is_queued(&result) => {
+          comment_view.update(|cv| cv.saved = !cv.saved);
+        }
         Err(_e) => {}
       }
     });
@@ -238,10 +239,15 @@ pub fn Comment(
           now_in_millis.set(u64::try_from(jiff::Zoned::now().timestamp().as_millisecond()).unwrap_or(0));
           children.update(|cs| cs.push(o.comment_view));
           #[cfg(not(feature = "ssr"))]
-          if let Ok(d) = IndexedDb::new().await {
+          if let Ok(d) = IndexedDb::shared().await {
             if let Ok(_c) = d.del(&CommentDraftKey { comment_id: comment_view.get().comment.id.0, draft: Draft::Reply }).await {}
           }
         }
+        // the draft is kept until the queued reply is sent
+        Err(_) if is_queued(&result) => {
+          loading.set(false);
+          reply_show.set(false);
+        }
         Err(_e) => {
           loading.set(false);
         }
@@ -260,10 +266,14 @@ pub fn Comment(
           loading.set(false);
           edit_show.set(false);
           #[cfg(not(feature = "ssr"))]
-          if let Ok(d) = IndexedDb::new().await {
+          if let Ok(d) = IndexedDb::shared().await {
             if let Ok(_c) = d.del(&CommentDraftKey { comment_id: comment_view.get().comment.id.0, draft: Draft::Edit }).await {}
           }
         }
+        Err(_) if is_queued(&result) => {
+          loading.set(false);
+          edit_show.set(false);
+        }
         Err(_e) => {
           loading.set(false);
         }
@@ -277,7 +287,85 @@ pub fn Comment(
     edit_show.set(false);
   };
 
+  let permalink = move || format!("/p/{}/c/{}", comment_view.get().post.id, comment_view.get().comment.id);
+
+  let on_permalink_click = move |e: MouseEvent| {
+    e.stop_propagation();
+    let _ = window().navigator().clipboard().write_text(&format!("{}{}", window().location().origin().unwrap_or_default(), permalink()));
+  };
+
+  let open_reply = move || {
+    edit_show.set(false);
+    reply_show.update(|b| *b = !*b);
+    spawn_local_scoped_with_cancellation(async move {
+      #[cfg(not(feature = "ssr"))]
+      if let Ok(d) = IndexedDb::shared().await {
+        if let Ok(Some(c)) = d
+          .get::<CommentDraftKey, CommentDraft>(
+            &CommentDraftKey {
+              comment_id: comment_view.get().comment.id.0,
+              draft: Draft::Reply,
+            },
+          )
+          .await
+        {
+          reply_content.set(c.value);
+        }
+      }
+    });
+  };
+
+  let open_edit = move || {
+    reply_show.set(false);
+    edit_show.update(|b| *b = !*b);
+    spawn_local_scoped_with_cancellation(async move {
+      #[cfg(not(feature = "ssr"))]
+      if let Ok(d) = IndexedDb::shared().await {
+        if let Ok(Some(c)) = d
+          .get::<CommentDraftKey, CommentDraft>(
+            &CommentDraftKey {
+              comment_id: comment_view.get().comment.id.0,
+              draft: Draft::Edit,
+            },
+          )
+          .await
+        {
+          edit_content.set(c.value);
+        } else {
+          edit_content.set(comment_view.get_untracked().comment.content);
+        }
+      }
+    });
+  };
+
+  let is_highlighted = move || highlight_comment_id.get().eq(&Some(comment_view.with_untracked(|cv| cv.comment.id.0)));
+  let comment_element = NodeRef::<Div>::new();
+
+  #[cfg(not(feature = "ssr"))]
+  Effect::new(move |_| {
+    if is_highlighted() {
+      if let Some(c) = comment_element.get() {
+        c.scroll_into_view();
+      }
+    }
+  });
+
+  // resuming a draft from the drafts page opens its editor straight away
+  #[cfg(not(feature = "ssr"))]
+  let query = use_query_map();
+  #[cfg(not(feature = "ssr"))]
+  Effect::new(move |_| {
+    if is_highlighted() {
+      match query.get_untracked().get("draft").as_deref() {
+        Some("reply") => open_reply(),
+        Some("edit") => open_edit(),
+        _ => {}
+      }
+    }
+  });
+
   let _visibility_element = NodeRef::<Textarea>::new();
+  let edit_element = NodeRef::<Textarea>::new();
 
   #[cfg(not(feature = "ssr"))]
   {
@@ -341,9 +429,10 @@ pub fn Comment(
     >
     // <span>{{move || is_there.get() }}</span>
       <div
+        node_ref={comment_element}
         class={move || {
           format!(
-            "pb-2 cursor-pointer{}{}",
+            "pb-2 cursor-pointer{}{}{}",
             if comment_view.get().creator.id.eq(&comment_view.get().post.creator_id) { " border-l-4 pl-2 border-accent" } else { "" },
             if highlight_user_id.get().is_some() && highlight_user_id.get().eq(&Some(comment_view.get().creator.id)) {
               " border-l-4 pl-2"
@@ -352,6 +441,7 @@ pub fn Comment(
             } else {
               ""
             },
+            if is_highlighted() { " bg-base-300 rounded" } else { "" },
           )
         }}
         on:click={move |e: MouseEvent| {
@@ -359,7 +449,8 @@ pub fn Comment(
             still_down.set(false);
           } else {
             if let Some(t) = e.target() {
-              if let Some(i) = t.dyn_ref::<HtmlImageElement>() {
+              if copy_code(&t) {
+              } else if let Some(i) = t.dyn_ref::<HtmlImageElement>() {
                 let _ = window().open_with_url_and_target(&i.src(), "_blank");
               } else if let Some(l) = t.dyn_ref::<HtmlAnchorElement>() {
                 e.prevent_default();
@@ -567,11 +658,11 @@ pub fn Comment(
                               format!(
                                 "{}{}",
                                 { if Some(1) == comment_view.get_untracked().my_vote { "text-secondary" } else { "" } },
-                                { if !logged_in.get() || !online.get().0 { " text-base-content/50" } else { " hover:text-secondary/50" } },
+                                { if !logged_in.get() { " text-base-content/50" } else { " hover:text-secondary/50" } },
                               )
                             }}
                             title="Up vote"
-                            disabled={move || !logged_in.get() || !online.get().0}
+                            disabled={move || !logged_in.get()}
                             on:click={on_up_vote_submit}
                           >
                             <Icon icon={Upvote} />
@@ -587,11 +678,11 @@ pub fn Comment(
                               format!(
                                 "{}{}",
                                 { if Some(-1) == comment_view.get_untracked().my_vote { "text-primary" } else { "" } },
-                                { if !logged_in.get() || !online.get().0 { " text-base-content/50" } else { " hover:text-primary/50" } },
+                                { if !logged_in.get() { " text-base-content/50" } else { " hover:text-primary/50" } },
                               )
                             }}
                             title="Down vote"
-                            disabled={move || !logged_in.get() || !online.get().0}
+                            disabled={move || !logged_in.get()}
                             on:click={on_down_vote_submit}
                           >
                             <Icon icon={Downvote} />
@@ -607,67 +698,27 @@ pub fn Comment(
                               format!(
                                 "{}{}",
                                 { if comment_view.get_untracked().saved { "text-accent" } else { "" } },
-                                { if !logged_in.get() || !online.get().0 { " text-base-content/50" } else { " hover:text-accent/50" } },
+                                { if !logged_in.get() { " text-base-content/50" } else { " hover:text-accent/50" } },
                               )
                             }}
-                            disabled={move || !logged_in.get() || !online.get().0}
+                            disabled={move || !logged_in.get()}
                             on:click={on_save_submit}
                           >
                             <Icon icon={Save} />
                           </button>
                         </Form>
                         <button
-                          on:click={move |_| {
-                            edit_show.set(false);
-                            reply_show.update(|b| *b = !*b);
-                            spawn_local_scoped_with_cancellation(async move {
-                              #[cfg(not(feature = "ssr"))]
-                              if let Ok(d) = IndexedDb::new().await {
-                                if let Ok(Some(c)) = d
-                                  .get(
-                                    &CommentDraftKey {
-                                      comment_id: comment_view.get().comment.id.0,
-                                      draft: Draft::Reply,
-                                    },
-                                  )
-                                  .await
-                                {
-                                  reply_content.set(c);
-                                }
-                              }
-                            });
-                          }}
+                          on:click={move |_| open_reply()}
                           title="Reply"
                           class={move || {
-                            format!("{}", { if !logged_in.get() || !online.get().0 { " text-base-content/50" } else { " hover:text-accent/50" } })
+                            format!("{}", { if !logged_in.get() { " text-base-content/50" } else { " hover:text-accent/50" } })
                           }}
-                          disabled={move || !logged_in.get() || !online.get().0}
+                          disabled={move || !logged_in.get()}
                         >
                           <Icon icon={Reply} />
                         </button>
                         <button
-                          on:click={move |_| {
-                            reply_show.set(false);
-                            edit_show.update(|b| *b = !*b);
-                            spawn_local_scoped_with_cancellation(async move {
-                              #[cfg(not(feature = "ssr"))]
-                              if let Ok(d) = IndexedDb::new().await {
-                                if let Ok(Some(c)) = d
-                                  .get(
-                                    &CommentDraftKey {
-                                      comment_id: comment_view.get().comment.id.0,
-                                      draft: Draft::Edit,
-                                    },
-                                  )
-                                  .await
-                                {
-                                  edit_content.set(c);
-                                } else {
-                                  edit_content.set(comment_view.get_untracked().comment.content);
-                                }
-                              }
-                            });
-                          }}
+                          on:click={move |_| open_edit()}
                           class={move || {
                             format!(
                               "{}{}",
@@ -676,10 +727,10 @@ pub fn Comment(
                               } else {
                                 "pointer-events-none text-base-content/50"
                               },
-                              { if !logged_in.get() || !online.get().0 { " text-base-content/50" } else { " hover:text-accent/50" } },
+                              { if !logged_in.get() { " text-base-content/50" } else { " hover:text-accent/50" } },
                             )
                           }}
-                          disabled={move || !logged_in.get() || !online.get().0}
+                          disabled={move || !logged_in.get()}
                           title="Edit"
                         >
                           <Icon icon={Pencil} />
@@ -696,6 +747,9 @@ pub fn Comment(
                         >
                           <Icon icon={Highlighter} />
                         </span>
+                        <span on:click={on_permalink_click} title="Copy permalink">
+                          <Icon icon={Link} />
+                        </span>
                       }
                     }
                       .into_any()
@@ -706,7 +760,9 @@ pub fn Comment(
             </Transition>
 
             <span class="overflow-hidden wrap-anywhere">
-              <span>{abbr_duration.clone()}</span>
+              <A href={permalink} attr:class="text-sm hover:text-secondary">
+                {abbr_duration.clone()}
+              </A>
               " ago by "
               <A
                 href={move || {
@@ -731,41 +787,20 @@ pub fn Comment(
         <div class="mb-3 space-y-3 break-inside-avoid">
           <Show when={move || reply_show.get()} fallback={|| {}}>
             <div class="form-control">
-              <textarea
-                class="h-24 w-full text-base textarea textarea-bordered"
-                placeholder="Comment text"
-                prop:value={move || reply_content.get()}
-                node_ref={_visibility_element}
-                on:wheel={move |e: WheelEvent| {
-                  e.stop_propagation();
-                }}
-                on:input={move |ev| {
-                  reply_content.set(event_target_value(&ev));
-                  spawn_local_scoped_with_cancellation(async move {
-                    #[cfg(not(feature = "ssr"))]
-                    if let Ok(d) = IndexedDb::new().await {
-                      if let Ok(_comment_ids) = d
-                        .set(
-                          &CommentDraftKey {
-                            comment_id: comment_view.get().comment.id.0,
-                            draft: Draft::Reply,
-                          },
-                          &reply_content.get(),
-                        )
-                        .await
-                      {} else {}
-                    }
-                  });
-                }}
-              >
-                {reply_content.get_untracked()}
-              </textarea>
+              <MarkdownEditor
+                content={reply_content}
+                textarea={_visibility_element}
+                draft_key={Signal::derive(move || Some(CommentDraftKey { comment_id: comment_view.get().comment.id.0, draft: Draft::Reply }))}
+                draft_post_id={Signal::derive(move || Some(comment_view.get().comment.post_id.0))}
+                draft_context={draft_context}
+                participants={thread_participants}
+              />
             </div>
             <div class="form-control">
               <button
                 on:click={on_reply_click}
                 type="button"
-                disabled={move || !online.get().0}
+                disabled={move || loading.get()}
                 class={move || format!("btn btn-neutral{}", if loading.get() { " btn-disabled" } else { "" })}
               >
                 "Reply"
@@ -777,41 +812,21 @@ pub fn Comment(
           </Show>
           <Show when={move || edit_show.get()} fallback={|| {}}>
             <div class="form-control">
-              <textarea
-                class="h-24 w-full text-base textarea textarea-bordered"
-                placeholder="Comment text"
-                prop:value={move || edit_content.get()}
-                on:wheel={move |e: WheelEvent| {
-                  e.stop_propagation();
-                }}
-                on:input={move |ev| {
-                  edit_content.set(event_target_value(&ev));
-                  comment_view.update(|cv| cv.comment.content = event_target_value(&ev));
-                  spawn_local_scoped_with_cancellation(async move {
-                    #[cfg(not(feature = "ssr"))]
-                    if let Ok(d) = IndexedDb::new().await {
-                      if let Ok(_comment_ids) = d
-                        .set(
-                          &CommentDraftKey {
-                            comment_id: comment_view.get().comment.id.0,
-                            draft: Draft::Edit,
-                          },
-                          &edit_content.get(),
-                        )
-                        .await
-                      {}
-                    }
-                  });
-                }}
-              >
-                {edit_content.get_untracked()}
-              </textarea>
+              <MarkdownEditor
+                content={edit_content}
+                textarea={edit_element}
+                draft_key={Signal::derive(move || Some(CommentDraftKey { comment_id: comment_view.get().comment.id.0, draft: Draft::Edit }))}
+                draft_post_id={Signal::derive(move || Some(comment_view.get().comment.post_id.0))}
+                draft_context={draft_context}
+                on_change={move |c: String| comment_view.update(|cv| cv.comment.content = c)}
+                participants={thread_participants}
+              />
             </div>
             <div class="form-control">
               <button
                 on:click={on_edit_click}
                 type="button"
-                disabled={move || !online.get().0}
+                disabled={move || loading.get()}
                 class={move || format!("btn btn-neutral{}", if loading.get() { " btn-disabled" } else { "" })}
               >
                 "Edit"
@@ -835,6 +850,7 @@ pub fn Comment(
           highlight_user_id
           post_id
           selected_drag_offset
+          highlight_comment_id
         />
       </For>
     </div>
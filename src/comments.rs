@@ -3,9 +3,15 @@ use lemmy_api_common::lemmy_db_views::structs::CommentView;
 use leptos::{prelude::*, task::spawn_local_scoped_with_cancellation};
 
 #[component]
-pub fn Comments(comments: Signal<Vec<CommentView>>, post_id: Signal<Option<i32>>) -> impl IntoView {
+pub fn Comments(
+  comments: Signal<Vec<CommentView>>,
+  post_id: Signal<Option<i32>>,
+  #[prop(optional)] highlight_comment_id: Signal<Option<i32>>,
+) -> impl IntoView {
   let mut comments_clone = comments.get().clone();
-  comments_clone.retain(|ct| ct.comment.path.chars().filter(|c| *c == '.').count() == 1);
+  // a context view starts part way down the tree, so the shallowest comments become the top level
+  let root_level = comments_clone.iter().map(|ct| ct.comment.path.chars().filter(|c| *c == '.').count()).min().unwrap_or(1);
+  comments_clone.retain(|ct| ct.comment.path.chars().filter(|c| *c == '.').count() == root_level);
   let com_sig = RwSignal::new(comments_clone);
   let highlight_user_id = RwSignal::new(None);
 
@@ -26,8 +32,14 @@ pub fn Comments(comments: Signal<Vec<CommentView>>, post_id: Signal<Option<i32>>
   #[cfg(not(feature = "ssr"))]
   spawn_local_scoped_with_cancellation(async move {
     if let Some(p) = post_id.get() {
-      if let Ok(d) = IndexedDb::new().await {
-        if let Ok(Some(comment_ids)) = d.get::<i32, Vec<i32>>(&p).await {
+      if let Ok(d) = IndexedDb::shared().await {
+        if let Ok(Some(mut comment_ids)) = d.get::<i32, Vec<i32>>(&p).await {
+          if let Some(h) = highlight_comment_id.get_untracked() {
+            if let Some(cv) = comments.get_untracked().iter().find(|cv| cv.comment.id.0 == h) {
+              let ancestors = cv.comment.path.split('.').filter_map(|s| s.parse::<i32>().ok()).collect::<Vec<_>>();
+              comment_ids.retain(|c| !ancestors.contains(c));
+            }
+          }
           hidden_comments.set(comment_ids);
         }
       }
@@ -41,9 +53,10 @@ pub fn Comments(comments: Signal<Vec<CommentView>>, post_id: Signal<Option<i32>>
         hidden_comments
         comment={cv.into()}
         comments={comments.get().into()}
-        level=1
+        level={root_level}
         now_in_millis
         highlight_user_id
+        highlight_comment_id
         post_id
         selected_drag_offset={RwSignal::new((1, 0f64, 0))}
       />
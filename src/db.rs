@@ -3,30 +3,122 @@ pub mod csr_indexed_db {
   use serde::{Deserialize, Serialize, de::DeserializeOwned};
   use thiserror::Error;
 
-  #[derive(Debug, Serialize, Deserialize)]
+  #[derive(Clone, Debug, Serialize, Deserialize)]
+  #[serde(from = "StoredDraft")]
   pub struct CommentDraft {
-    pub value: Option<String>,
+    pub value: String,
+    pub post_id: Option<i32>,
+    pub context: String,
+    pub modified: i64,
   }
 
-  #[derive(Clone, Debug, Serialize, Deserialize)]
+  impl CommentDraft {
+    pub fn new(value: String, post_id: Option<i32>, context: String) -> Self {
+      Self { value, post_id, context, modified: jiff::Timestamp::now().as_millisecond() }
+    }
+  }
+
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum StoredDraft {
+    Draft { value: String, post_id: Option<i32>, context: String, modified: i64 },
+    // drafts saved before they carried any context
+    Value(String),
+  }
+
+  impl From<StoredDraft> for CommentDraft {
+    fn from(stored: StoredDraft) -> Self {
+      match stored {
+        StoredDraft::Draft { value, post_id, context, modified } => Self { value, post_id, context, modified },
+        StoredDraft::Value(value) => Self { value, post_id: None, context: String::new(), modified: 0 },
+      }
+    }
+  }
+
+  #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
   pub enum Draft {
     Edit,
     Reply,
     Post,
   }
 
-  #[derive(Debug, Clone, Serialize, Deserialize)]
+  #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
   pub struct CommentDraftKey {
     pub comment_id: i32,
     pub draft: Draft,
   }
 
-  #[derive(Debug, Clone, Serialize, Deserialize)]
+  #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
   pub struct ScrollPositionKey {
     pub path: String,
     pub query: String,
   }
 
+  #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+  pub struct PendingActionKey {
+    pub queued: i64,
+  }
+
+  // mutations made while offline, replayed in key order once back online
+  #[derive(Debug, Clone, Serialize, Deserialize)]
+  pub enum PendingAction {
+    LikePost(CreatePostLike),
+    LikeComment(CreateCommentLike),
+    SavePost(SavePost),
+    SaveComment(SaveComment),
+    ReplyComment(CreateComment),
+    EditComment(EditComment),
+  }
+
+  impl Store for PendingActionKey {
+    fn store_name(&self) -> &'static str {
+      "pending_actions"
+    }
+  }
+
+  #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+  pub struct CacheMetaKey {
+    pub store: String,
+    pub key: String,
+  }
+
+  #[derive(Debug, Clone, Serialize, Deserialize)]
+  pub struct CacheMeta {
+    pub written: i64,
+    pub accessed: i64,
+    pub size: usize,
+  }
+
+  impl Store for CacheMetaKey {
+    fn store_name(&self) -> &'static str {
+      "cache_meta"
+    }
+  }
+
+  pub struct CachePolicy {
+    pub ttl_millis: i64,
+    pub max_entries: usize,
+    pub max_bytes: usize,
+  }
+
+  pub const CACHED_STORES: [&str; 2] = ["query_gets", "scroll_positions"];
+
+  // only stores holding data that can be fetched or recomputed again expire, drafts and the like are kept until deleted
+  pub fn cache_policy(store_name: &str) -> Option<CachePolicy> {
+    match store_name {
+      "query_gets" => Some(CachePolicy { ttl_millis: 7 * 24 * 60 * 60 * 1000, max_entries: 500, max_bytes: 25 * 1024 * 1024 }),
+      "scroll_positions" => Some(CachePolicy { ttl_millis: 30 * 24 * 60 * 60 * 1000, max_entries: 200, max_bytes: 256 * 1024 }),
+      _ => None,
+    }
+  }
+
+  #[derive(Debug, Clone, Default)]
+  pub struct CacheUsage {
+    pub store: String,
+    pub entries: usize,
+    pub bytes: usize,
+  }
+
   impl Store for ScrollPositionKey {
     fn store_name(&self) -> &'static str {
       "scroll_positions"
@@ -200,7 +292,26 @@ pub mod csr_indexed_db {
   }
 
   #[cfg(not(feature = "ssr"))]
-  use rexie::{ObjectStore, Rexie, TransactionMode};
+  use rexie::{ObjectStore, Rexie, Store as RexieStore, TransactionMode};
+  #[cfg(not(feature = "ssr"))]
+  use leptos::prelude::*;
+  #[cfg(not(feature = "ssr"))]
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  // eviction scans every cache entry's metadata so it only runs every so many cached writes
+  #[cfg(not(feature = "ssr"))]
+  const EVICT_EVERY: usize = 64;
+  #[cfg(not(feature = "ssr"))]
+  static CACHED_WRITES: AtomicUsize = AtomicUsize::new(0);
+
+  // cache hits only note when they were read, and the notes are written out together, so reads never wait on a
+  // readwrite transaction
+  #[cfg(not(feature = "ssr"))]
+  const ACCESS_FLUSH_EVERY: usize = 32;
+  #[cfg(not(feature = "ssr"))]
+  thread_local! {
+    static PENDING_ACCESSES: std::cell::RefCell<std::collections::HashMap<String, i64>> = std::cell::RefCell::new(std::collections::HashMap::new());
+  }
 
   #[derive(Debug, Error)]
   pub enum Error {
@@ -213,6 +324,9 @@ pub mod csr_indexed_db {
     // #[cfg(not(feature = "ssr"))]
     #[error("serde json error: {0}")]
     SerdeJson(#[from] serde_json::Error),
+    #[cfg(not(feature = "ssr"))]
+    #[error("open error: {0}")]
+    Open(String),
   }
 
   #[derive(Clone)]
@@ -221,23 +335,171 @@ pub mod csr_indexed_db {
     pub rexie: Rexie,
   }
 
+  #[cfg(not(feature = "ssr"))]
+  enum Step {
+    CreateStore(&'static str),
+    #[allow(dead_code)]
+    DeleteStore(&'static str),
+    ClearStore(&'static str),
+    DeleteDatabase(&'static str),
+  }
+
+  #[cfg(not(feature = "ssr"))]
+  use Step::*;
+
+  #[cfg(not(feature = "ssr"))]
+  const DATABASE: &str = "cache_v5";
+
+  // append only, entry n upgrades the database to version n + 1. store changes are applied by the version bump, the
+  // rest runs once after opening. comment_drafts, post_closed_comments and pending_actions hold user data so must
+  // never be cleared or deleted here
+  #[cfg(not(feature = "ssr"))]
+  const MIGRATIONS: &[&[Step]] = &[
+    &[CreateStore("post_closed_comments"), CreateStore("comment_drafts"), CreateStore("query_gets"), CreateStore("scroll_positions")],
+    &[CreateStore("pending_actions")],
+    // cached entries written before this carry no metadata so could never expire
+    &[CreateStore("cache_meta"), ClearStore("query_gets"), ClearStore("scroll_positions")],
+    // schema changes used to be made by renaming the database, leaving the old ones behind
+    &[CreateStore("schema"), DeleteDatabase("cache_v1"), DeleteDatabase("cache_v2"), DeleteDatabase("cache_v3"), DeleteDatabase("cache_v4")],
+  ];
+
+  #[cfg(not(feature = "ssr"))]
+  fn schema_stores() -> Vec<&'static str> {
+    let mut stores = vec![];
+    for step in MIGRATIONS.iter().flat_map(|m| m.iter()) {
+      match step {
+        CreateStore(s) => stores.push(*s),
+        DeleteStore(s) => stores.retain(|t| t != s),
+        _ => {}
+      }
+    }
+    stores
+  }
+
   #[cfg(not(feature = "ssr"))]
   impl IndexedDb {
     pub async fn new() -> Result<Self, Error> {
-      let rexie = Rexie::builder("cache_v5")
-        .version(1)
-        .add_object_store(ObjectStore::new("post_closed_comments"))
-        .add_object_store(ObjectStore::new("comment_drafts"))
-        .add_object_store(ObjectStore::new("query_gets"))
-        .add_object_store(ObjectStore::new("scroll_positions"))
-        .build()
-        .await?;
-      Ok(Self { rexie })
+      let mut builder = Rexie::builder(DATABASE).version(MIGRATIONS.len() as u32);
+      for store_name in schema_stores() {
+        builder = builder.add_object_store(ObjectStore::new(store_name));
+      }
+      let db = Self { rexie: builder.build().await? };
+      db.migrate().await?;
+      Ok(db)
+    }
+
+    async fn migrate(&self) -> Result<(), Error> {
+      let version_key = serde_wasm_bindgen::to_value("version")?;
+      let transaction = self.rexie.transaction(&["schema"], TransactionMode::ReadOnly)?;
+      let applied = transaction.store("schema")?.get(version_key.clone()).await?.and_then(|v| serde_wasm_bindgen::from_value::<usize>(v).ok()).unwrap_or(0);
+      if applied >= MIGRATIONS.len() {
+        return Ok(());
+      }
+      let stores = schema_stores();
+      for step in MIGRATIONS[applied..].iter().flat_map(|m| m.iter()) {
+        match step {
+          ClearStore(s) if stores.contains(s) => self.clear(s).await?,
+          // another tab may still have the old database open, which would block this until it closes
+          DeleteDatabase(name) => {
+            let name = *name;
+            leptos::task::spawn_local(async move {
+              let _ = Rexie::delete(name).await;
+            });
+          }
+          _ => {}
+        }
+      }
+      let transaction = self.rexie.transaction(&["schema"], TransactionMode::ReadWrite)?;
+      transaction.store("schema")?.put(&serde_wasm_bindgen::to_value(&MIGRATIONS.len())?, Some(&version_key)).await?;
+      transaction.done().await?;
+      Ok(())
+    }
+  }
+
+  #[cfg(not(feature = "ssr"))]
+  const SCROLL_WRITE_DELAY_MILLIS: u64 = 500;
+
+  // one connection for the whole app, opened on first use and provided as context by App
+  #[cfg(not(feature = "ssr"))]
+  type Opening = futures::future::Shared<futures::future::LocalBoxFuture<'static, Result<IndexedDb, String>>>;
+
+  #[cfg(not(feature = "ssr"))]
+  #[derive(Clone, Copy)]
+  pub struct SharedIndexedDb {
+    db: StoredValue<Option<IndexedDb>, LocalStorage>,
+    opening: StoredValue<Option<Opening>, LocalStorage>,
+    scroll_positions: StoredValue<Vec<(ScrollPositionKey, i32)>>,
+    flush_scheduled: StoredValue<bool>,
+  }
+
+  #[cfg(not(feature = "ssr"))]
+  impl SharedIndexedDb {
+    pub fn new() -> Self {
+      Self {
+        db: StoredValue::new_local(None),
+        opening: StoredValue::new_local(None),
+        scroll_positions: StoredValue::new(vec![]),
+        flush_scheduled: StoredValue::new(false),
+      }
+    }
+
+    pub async fn handle(self) -> Result<IndexedDb, Error> {
+      use futures::future::FutureExt;
+
+      if let Some(d) = self.db.get_value() {
+        return Ok(d);
+      }
+      // callers arriving while the first open is under way wait on it rather than opening connections of their own
+      let opening = match self.opening.get_value() {
+        Some(o) => o,
+        None => {
+          let o = async { IndexedDb::new().await.map_err(|e| e.to_string()) }.boxed_local().shared();
+          self.opening.set_value(Some(o.clone()));
+          o
+        }
+      };
+      let result = opening.await;
+      // a failed open is tried again by the next caller
+      self.opening.set_value(None);
+      let d = result.map_err(Error::Open)?;
+      self.db.set_value(Some(d.clone()));
+      Ok(d)
+    }
+
+    // scroll events fire many times a second, so the latest position per page is kept and written in one go once
+    // scrolling settles
+    pub fn save_scroll_position(self, key: ScrollPositionKey, position: i32) {
+      self.scroll_positions.update_value(|p| {
+        p.retain(|(k, _)| *k != key);
+        p.push((key, position));
+      });
+      if self.flush_scheduled.get_value() {
+        return;
+      }
+      self.flush_scheduled.set_value(true);
+      set_timeout(
+        move || leptos::task::spawn_local(self.flush_scroll_positions()),
+        std::time::Duration::from_millis(SCROLL_WRITE_DELAY_MILLIS),
+      );
+    }
+
+    async fn flush_scroll_positions(self) {
+      self.flush_scheduled.set_value(false);
+      let positions = self.scroll_positions.try_update_value(std::mem::take).unwrap_or_default();
+      if !positions.is_empty()
+        && let Ok(d) = self.handle().await
+      {
+        let _ = d.set_all(&positions).await;
+      }
     }
   }
 
   #[cfg(feature = "ssr")]
   impl IndexedDb {
+    pub async fn shared() -> Result<Self, Error> {
+      Self::new().await
+    }
+
     pub async fn new() -> Result<Self, Error> {
       // let rexie = Rexie::builder("cache_v5")
       //   .version(1)
@@ -251,6 +513,40 @@ pub mod csr_indexed_db {
     }
   }
 
+  #[cfg(not(feature = "ssr"))]
+  fn now_in_millis() -> i64 {
+    jiff::Timestamp::now().as_millisecond()
+  }
+
+  #[cfg(not(feature = "ssr"))]
+  fn meta_key_string(store_name: &str, key: &str) -> Result<String, Error> {
+    Ok(serde_json::to_string(&CacheMetaKey { store: store_name.to_owned(), key: key.to_owned() })?)
+  }
+
+  #[cfg(not(feature = "ssr"))]
+  fn meta_key(store_name: &str, key: &str) -> Result<wasm_bindgen::JsValue, Error> {
+    Ok(serde_wasm_bindgen::to_value(&meta_key_string(store_name, key)?)?)
+  }
+
+  // entries that no longer match the expected shape are skipped rather than failing the whole list
+  #[cfg(not(feature = "ssr"))]
+  async fn scan_entries<Form, Response>(store: &RexieStore) -> Result<Vec<(Form, Response)>, Error>
+  where
+    Form: DeserializeOwned,
+    Response: DeserializeOwned,
+  {
+    let mut entries = vec![];
+    for (k, v) in store.scan(None, None, None, None).await? {
+      if let Ok(key) = serde_wasm_bindgen::from_value::<String>(k)
+        && let Ok(form) = serde_json::from_str::<Form>(&key)
+        && let Ok(response) = serde_wasm_bindgen::from_value::<Response>(v)
+      {
+        entries.push((form, response));
+      }
+    }
+    Ok(entries)
+  }
+
   #[cfg(not(feature = "ssr"))]
   impl IndexedDb {
     pub async fn get<Form, Response>(&self, key: &Form) -> Result<Option<Response>, Error>
@@ -258,12 +554,70 @@ pub mod csr_indexed_db {
       Form: Serialize + Store,
       Response: DeserializeOwned,
     {
-      let transaction = self.rexie.transaction(&[key.store_name()], TransactionMode::ReadOnly)?;
-      let comments = transaction.store(key.store_name())?;
-      if let Some(comment_meta_value) = comments.get(serde_wasm_bindgen::to_value(&serde_json::to_string(key)?)?).await? {
-        Ok(Some(serde_wasm_bindgen::from_value::<Response>(comment_meta_value)?))
-      } else {
-        Ok(None)
+      let Some(policy) = cache_policy(key.store_name()) else {
+        let transaction = self.rexie.transaction(&[key.store_name()], TransactionMode::ReadOnly)?;
+        let comments = transaction.store(key.store_name())?;
+        if let Some(comment_meta_value) = comments.get(serde_wasm_bindgen::to_value(&serde_json::to_string(key)?)?).await? {
+          return Ok(Some(serde_wasm_bindgen::from_value::<Response>(comment_meta_value)?));
+        } else {
+          return Ok(None);
+        }
+      };
+      let key_string = serde_json::to_string(key)?;
+      let value_key = serde_wasm_bindgen::to_value(&key_string)?;
+      let meta_key_string = meta_key_string(key.store_name(), &key_string)?;
+      let transaction = self.rexie.transaction(&[key.store_name(), "cache_meta"], TransactionMode::ReadOnly)?;
+      let meta = transaction
+        .store("cache_meta")?
+        .get(serde_wasm_bindgen::to_value(&meta_key_string)?)
+        .await?
+        .and_then(|m| serde_wasm_bindgen::from_value::<CacheMeta>(m).ok());
+      let value = transaction.store(key.store_name())?.get(value_key).await?;
+      let now = now_in_millis();
+      match meta {
+        Some(meta) if now - meta.written < policy.ttl_millis => {
+          let pending = PENDING_ACCESSES.with_borrow_mut(|p| {
+            p.insert(meta_key_string, now);
+            p.len()
+          });
+          if pending >= ACCESS_FLUSH_EVERY {
+            self.flush_accesses().await?;
+          }
+          Ok(value.map(serde_wasm_bindgen::from_value::<Response>).transpose()?)
+        }
+        // expired, or written before entries were timestamped
+        _ => {
+          if meta.is_some() || value.is_some() {
+            self.del(key).await?;
+          }
+          Ok(None)
+        }
+      }
+    }
+
+    // writes the noted access times into the metadata of entries that are still there
+    pub async fn flush_accesses(&self) -> Result<(), Error> {
+      let accesses = PENDING_ACCESSES.with_borrow_mut(std::mem::take);
+      if accesses.is_empty() {
+        return Ok(());
+      }
+      let transaction = self.rexie.transaction(&["cache_meta"], TransactionMode::ReadWrite)?;
+      let metas = transaction.store("cache_meta")?;
+      for (key, accessed) in accesses {
+        let key = serde_wasm_bindgen::to_value(&key)?;
+        if let Some(mut meta) = metas.get(key.clone()).await?.and_then(|m| serde_wasm_bindgen::from_value::<CacheMeta>(m).ok()) {
+          meta.accessed = meta.accessed.max(accessed);
+          metas.put(&serde_wasm_bindgen::to_value(&meta)?, Some(&key)).await?;
+        }
+      }
+      transaction.done().await?;
+      Ok(())
+    }
+
+    pub async fn shared() -> Result<Self, Error> {
+      match use_context::<SharedIndexedDb>() {
+        Some(shared) => shared.handle().await,
+        None => Self::new().await,
       }
     }
 
@@ -272,13 +626,7 @@ pub mod csr_indexed_db {
       Form: Serialize + Store,
       Response: DeserializeOwned,
     {
-      let transaction = self.rexie.transaction(&[key.store_name()], TransactionMode::ReadOnly)?;
-      let comments = transaction.store(key.store_name())?;
-      if let Some(comment_meta_value) = comments.get(serde_wasm_bindgen::to_value(&serde_json::to_string(key)?)?).await? {
-        Ok(Some(serde_wasm_bindgen::from_value::<Response>(comment_meta_value)?))
-      } else {
-        Ok(None)
-      }
+      self.get(key).await
     }
 
     pub async fn set<Form, Response>(&self, key: &Form, t: &Response) -> Result<(), Error>
@@ -286,11 +634,128 @@ pub mod csr_indexed_db {
       Form: Serialize + Store,
       Response: Serialize,
     {
-      let transaction = self.rexie.transaction(&[key.store_name()], TransactionMode::ReadWrite)?;
-      let posts = transaction.store(key.store_name())?;
       let post_meta_value = serde_wasm_bindgen::to_value(t)?;
-      let post_meta_key = serde_wasm_bindgen::to_value(&serde_json::to_string(key)?)?;
-      let _id = posts.put(&post_meta_value, Some(&post_meta_key)).await?;
+      let key_string = serde_json::to_string(key)?;
+      let post_meta_key = serde_wasm_bindgen::to_value(&key_string)?;
+      if cache_policy(key.store_name()).is_none() {
+        let transaction = self.rexie.transaction(&[key.store_name()], TransactionMode::ReadWrite)?;
+        let posts = transaction.store(key.store_name())?;
+        let _id = posts.put(&post_meta_value, Some(&post_meta_key)).await?;
+        transaction.done().await?;
+        return Ok(());
+      }
+      let now = now_in_millis();
+      let meta = CacheMeta { written: now, accessed: now, size: serde_json::to_vec(t)?.len() };
+      let transaction = self.rexie.transaction(&[key.store_name(), "cache_meta"], TransactionMode::ReadWrite)?;
+      transaction.store(key.store_name())?.put(&post_meta_value, Some(&post_meta_key)).await?;
+      transaction.store("cache_meta")?.put(&serde_wasm_bindgen::to_value(&meta)?, Some(&meta_key(key.store_name(), &key_string)?)).await?;
+      transaction.done().await?;
+      if CACHED_WRITES.fetch_add(1, Ordering::Relaxed) % EVICT_EVERY == 0 {
+        self.evict().await?;
+      }
+      Ok(())
+    }
+
+    // writes several entries of one store in a single transaction
+    pub async fn set_all<Form, Response>(&self, entries: &[(Form, Response)]) -> Result<(), Error>
+    where
+      Form: Serialize + Store,
+      Response: Serialize,
+    {
+      let Some(store_name) = entries.first().map(|(k, _)| k.store_name()) else {
+        return Ok(());
+      };
+      let cached = cache_policy(store_name).is_some();
+      let store_names = if cached { vec![store_name, "cache_meta"] } else { vec![store_name] };
+      let transaction = self.rexie.transaction(&store_names, TransactionMode::ReadWrite)?;
+      let now = now_in_millis();
+      for (key, t) in entries {
+        let key_string = serde_json::to_string(key)?;
+        transaction.store(store_name)?.put(&serde_wasm_bindgen::to_value(t)?, Some(&serde_wasm_bindgen::to_value(&key_string)?)).await?;
+        if cached {
+          let meta = CacheMeta { written: now, accessed: now, size: serde_json::to_vec(t)?.len() };
+          transaction.store("cache_meta")?.put(&serde_wasm_bindgen::to_value(&meta)?, Some(&meta_key(store_name, &key_string)?)).await?;
+        }
+      }
+      transaction.done().await?;
+      if cached && CACHED_WRITES.fetch_add(entries.len(), Ordering::Relaxed) % EVICT_EVERY < entries.len() {
+        self.evict().await?;
+      }
+      Ok(())
+    }
+
+    // drops expired entries, then the least recently used ones until each cached store fits its entry and size limits
+    pub async fn evict(&self) -> Result<(), Error> {
+      self.flush_accesses().await?;
+      let now = now_in_millis();
+      // the metas are read in the transaction that deletes, so nothing written in between goes unaccounted for
+      let transaction = self.rexie.transaction(&[CACHED_STORES[0], CACHED_STORES[1], "cache_meta"], TransactionMode::ReadWrite)?;
+      let meta_store = transaction.store("cache_meta")?;
+      let mut metas = scan_entries::<CacheMetaKey, CacheMeta>(&meta_store).await?;
+      metas.sort_by(|a, b| b.1.accessed.cmp(&a.1.accessed));
+      for store_name in CACHED_STORES {
+        let Some(policy) = cache_policy(store_name) else {
+          continue;
+        };
+        let store = transaction.store(store_name)?;
+        let (mut entries, mut bytes) = (0usize, 0usize);
+        let mut metered = std::collections::HashSet::new();
+        for (k, meta) in metas.iter().filter(|(k, _)| k.store == store_name) {
+          metered.insert(k.key.clone());
+          if now - meta.written < policy.ttl_millis && entries < policy.max_entries && bytes + meta.size <= policy.max_bytes {
+            entries += 1;
+            bytes += meta.size;
+          } else {
+            store.delete(serde_wasm_bindgen::to_value(&k.key)?).await?;
+            meta_store.delete(meta_key(store_name, &k.key)?).await?;
+          }
+        }
+        // entries from before the cache kept metadata can never be accounted for
+        for k in store.get_all_keys(None, None).await? {
+          if let Some(key) = k.as_string()
+            && !metered.contains(&key)
+          {
+            store.delete(k).await?;
+          }
+        }
+      }
+      transaction.done().await?;
+      Ok(())
+    }
+
+    pub async fn usage(&self) -> Result<Vec<CacheUsage>, Error> {
+      let metas = self.entries::<CacheMetaKey, CacheMeta>("cache_meta").await?;
+      Ok(
+        CACHED_STORES
+          .iter()
+          .map(|store_name| {
+            let store_metas = metas.iter().filter(|(k, _)| k.store == *store_name).collect::<Vec<_>>();
+            CacheUsage { store: store_name.to_string(), entries: store_metas.len(), bytes: store_metas.iter().map(|(_, m)| m.size).sum() }
+          })
+          .collect(),
+      )
+    }
+
+    pub async fn clear_cache(&self) -> Result<(), Error> {
+      for store_name in CACHED_STORES {
+        self.clear(store_name).await?;
+      }
+      self.clear("cache_meta").await
+    }
+
+    pub async fn entries<Form, Response>(&self, store_name: &str) -> Result<Vec<(Form, Response)>, Error>
+    where
+      Form: DeserializeOwned,
+      Response: DeserializeOwned,
+    {
+      let transaction = self.rexie.transaction(&[store_name], TransactionMode::ReadOnly)?;
+      scan_entries(&transaction.store(store_name)?).await
+    }
+
+    pub async fn clear(&self, store_name: &str) -> Result<(), Error> {
+      let transaction = self.rexie.transaction(&[store_name], TransactionMode::ReadWrite)?;
+      let store = transaction.store(store_name)?;
+      store.clear().await?;
       transaction.done().await?;
       Ok(())
     }
@@ -299,10 +764,18 @@ pub mod csr_indexed_db {
     where
       Form: Serialize + Store,
     {
-      let transaction = self.rexie.transaction(&[key.store_name()], TransactionMode::ReadWrite)?;
-      let posts = transaction.store(key.store_name())?;
-      let post_meta_key = serde_wasm_bindgen::to_value(&serde_json::to_string(key)?)?;
-      let _id = posts.delete(post_meta_key).await?;
+      let key_string = serde_json::to_string(key)?;
+      let post_meta_key = serde_wasm_bindgen::to_value(&key_string)?;
+      if cache_policy(key.store_name()).is_none() {
+        let transaction = self.rexie.transaction(&[key.store_name()], TransactionMode::ReadWrite)?;
+        let posts = transaction.store(key.store_name())?;
+        let _id = posts.delete(post_meta_key).await?;
+        transaction.done().await?;
+        return Ok(());
+      }
+      let transaction = self.rexie.transaction(&[key.store_name(), "cache_meta"], TransactionMode::ReadWrite)?;
+      transaction.store(key.store_name())?.delete(post_meta_key).await?;
+      transaction.store("cache_meta")?.delete(meta_key(key.store_name(), &key_string)?).await?;
       transaction.done().await?;
       Ok(())
     }
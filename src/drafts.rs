@@ -0,0 +1,170 @@
+use crate::{
+  client::*,
+  db::csr_indexed_db::*,
+  emoji::custom_emojis,
+  errors::LemmyAppError,
+  icon::{Icon, IconType::*},
+  markdown::*,
+  nav::TopNav,
+};
+use lemmy_api_common::{comment::GetComment, lemmy_db_schema::newtypes::CommentId, site::GetSiteResponse};
+use leptos::{html::Div, prelude::*, task::spawn_local_scoped_with_cancellation};
+use leptos_meta::Title;
+use leptos_router::hooks::use_navigate;
+use web_sys::MouseEvent;
+
+fn draft_label(draft: &Draft) -> &'static str {
+  match draft {
+    Draft::Post => "Comment on post",
+    Draft::Reply => "Reply to comment",
+    Draft::Edit => "Edit of comment",
+  }
+}
+
+fn draft_query(draft: &Draft) -> &'static str {
+  match draft {
+    Draft::Post => "post",
+    Draft::Reply => "reply",
+    Draft::Edit => "edit",
+  }
+}
+
+fn modified_text(now: i64, modified: i64) -> String {
+  if modified == 0 {
+    return "unknown".into();
+  }
+  let duration_in_text = pretty_duration::pretty_duration(
+    &std::time::Duration::from_millis(u64::try_from(now - modified).unwrap_or(0)),
+    Some(pretty_duration::PrettyDurationOptions {
+      output_format: Some(pretty_duration::PrettyDurationOutputFormat::Compact),
+      singular_labels: None,
+      plural_labels: None,
+    }),
+  );
+  let abbr_duration =
+    if let Some((index, _)) = duration_in_text.match_indices(' ').nth(1) { duration_in_text.split_at(index) } else { (&duration_in_text[..], "") }
+      .0
+      .to_string();
+  format!("{} ago", abbr_duration)
+}
+
+#[component]
+pub fn Drafts() -> impl IntoView {
+  let ssr_site = expect_context::<Resource<Result<GetSiteResponse, LemmyAppError>>>();
+  let drafts: RwSignal<Vec<(CommentDraftKey, CommentDraft)>> = RwSignal::new(vec![]);
+  let loaded = RwSignal::new(false);
+  let now_in_millis = jiff::Timestamp::now().as_millisecond();
+  let on_scroll_element = NodeRef::<Div>::new();
+
+  #[cfg(not(feature = "ssr"))]
+  spawn_local_scoped_with_cancellation(async move {
+    if let Ok(d) = IndexedDb::shared().await
+      && let Ok(mut entries) = d.entries::<CommentDraftKey, CommentDraft>("comment_drafts").await
+    {
+      entries.retain(|(_, v)| v.value.trim().len() > 0);
+      entries.sort_by(|a, b| b.1.modified.cmp(&a.1.modified));
+      drafts.set(entries);
+    }
+    loaded.set(true);
+  });
+
+  let on_discard = move |key: CommentDraftKey| {
+    move |_e: MouseEvent| {
+      let key = key.clone();
+      #[cfg(not(feature = "ssr"))]
+      spawn_local_scoped_with_cancellation(async move {
+        if let Ok(d) = IndexedDb::shared().await
+          && let Ok(_) = d.del(&key).await
+        {
+          drafts.update(|ds| ds.retain(|(k, _)| *k != key));
+        }
+      });
+    }
+  };
+
+  let on_clear_all = move |_e: MouseEvent| {
+    if !window().confirm_with_message("Discard all drafts?").unwrap_or(false) {
+      return;
+    }
+    #[cfg(not(feature = "ssr"))]
+    spawn_local_scoped_with_cancellation(async move {
+      if let Ok(d) = IndexedDb::shared().await
+        && let Ok(_) = d.clear("comment_drafts").await
+      {
+        drafts.set(vec![]);
+      }
+    });
+  };
+
+  let on_resume = move |key: CommentDraftKey, post_id: Option<i32>| {
+    move |e: MouseEvent| {
+      e.prevent_default();
+      let key = key.clone();
+      spawn_local_scoped_with_cancellation(async move {
+        let post_id = match (&key.draft, post_id) {
+          (Draft::Post, _) => Some(key.comment_id),
+          (_, Some(p)) => Some(p),
+          // older drafts only know their comment, so look up which post it belongs to
+          (_, None) => LemmyClient.get_comment(GetComment { id: CommentId(key.comment_id) }).await.ok().map(|c| c.comment_view.comment.post_id.0),
+        };
+        if let Some(p) = post_id {
+          let href = match key.draft {
+            Draft::Post => format!("/p/{}?draft=post", p),
+            _ => format!("/p/{}/c/{}?draft={}", p, key.comment_id, draft_query(&key.draft)),
+          };
+          use_navigate()(&href, Default::default());
+        }
+      });
+    }
+  };
+
+  view! {
+    <main class="flex flex-col">
+      <Title text="Drafts" />
+      <TopNav scroll_element={on_scroll_element.into()} />
+      <div class="p-3 mx-auto w-full max-w-screen-md" node_ref={on_scroll_element}>
+        <div class="flex justify-between items-center mb-4">
+          <span class="text-2xl font-extrabold">"Drafts"</span>
+          <button type="button" class="btn btn-neutral btn-sm" disabled={move || drafts.get().is_empty()} on:click={on_clear_all}>
+            <Icon icon={Eraser} />
+            "Clear all"
+          </button>
+        </div>
+        <Show when={move || loaded.get() && drafts.get().is_empty()} fallback={|| {}}>
+          <div class="alert alert-info alert-soft">
+            <span>"No unsent drafts"</span>
+          </div>
+        </Show>
+        <For each={move || drafts.get()} key={|(k, v)| (k.comment_id, draft_query(&k.draft), v.modified)} let:entry>
+          {
+            let (key, draft) = entry;
+            let preview = markdown_to_html(&draft.value, Profile::Body, custom_emojis(ssr_site));
+            view! {
+              <div class="mb-3 shadow-sm card bg-base-200">
+                <div class="p-4 card-body">
+                  <div class="flex flex-wrap gap-2 justify-between items-center text-sm">
+                    <span class="badge badge-neutral">{draft_label(&key.draft)}</span>
+                    <span class="text-base-content/50">{modified_text(now_in_millis, draft.modified)}</span>
+                  </div>
+                  {(!draft.context.is_empty())
+                    .then(|| view! { <div class="text-sm italic truncate text-base-content/70">{draft.context.clone()}</div> })}
+                  <div class="overflow-hidden max-h-40 prose" inner_html={preview} />
+                  <div class="justify-end card-actions">
+                    <button type="button" class="btn btn-neutral btn-sm" on:click={on_resume(key.clone(), draft.post_id)}>
+                      <Icon icon={Pencil} />
+                      "Resume"
+                    </button>
+                    <button type="button" class="btn btn-ghost btn-sm" on:click={on_discard(key.clone())}>
+                      <Icon icon={Eraser} />
+                      "Discard"
+                    </button>
+                  </div>
+                </div>
+              </div>
+            }
+          }
+        </For>
+      </div>
+    </main>
+  }
+}
@@ -0,0 +1,257 @@
+use crate::{
+  db::csr_indexed_db::*,
+  emoji::*,
+  errors::LemmyAppError,
+  markdown::*,
+  mention::MentionPicker,
+};
+use lemmy_api_common::site::GetSiteResponse;
+use leptos::{html::Textarea, prelude::*, task::spawn_local_scoped_with_cancellation};
+use web_sys::{EventInit, HtmlTextAreaElement, KeyboardEvent, MouseEvent, WheelEvent};
+
+const MAX_COMMENT_LENGTH: usize = 10000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+  Bold,
+  Italic,
+  Link,
+  Quote,
+  Code,
+  Spoiler,
+  List,
+}
+
+impl Format {
+  fn title(self) -> &'static str {
+    match self {
+      Format::Bold => "Bold (Ctrl+B)",
+      Format::Italic => "Italic (Ctrl+I)",
+      Format::Link => "Link (Ctrl+K)",
+      Format::Quote => "Quote (Ctrl+Shift+.)",
+      Format::Code => "Code (Ctrl+E)",
+      Format::Spoiler => "Spoiler (Ctrl+Shift+X)",
+      Format::List => "List (Ctrl+Shift+8)",
+    }
+  }
+
+  fn label(self) -> &'static str {
+    match self {
+      Format::Bold => "B",
+      Format::Italic => "I",
+      Format::Link => "Link",
+      Format::Quote => ">",
+      Format::Code => "</>",
+      Format::Spoiler => "Spoiler",
+      Format::List => "List",
+    }
+  }
+
+  fn from_key(e: &KeyboardEvent) -> Option<Format> {
+    if !(e.ctrl_key() || e.meta_key()) || e.alt_key() {
+      return None;
+    }
+    match (e.shift_key(), e.code().as_str()) {
+      (false, "KeyB") => Some(Format::Bold),
+      (false, "KeyI") => Some(Format::Italic),
+      (false, "KeyK") => Some(Format::Link),
+      (false, "KeyE") => Some(Format::Code),
+      (true, "Period") => Some(Format::Quote),
+      (true, "KeyX") => Some(Format::Spoiler),
+      (true, "Digit8") => Some(Format::List),
+      _ => None,
+    }
+  }
+}
+
+pub fn byte_index(value: &str, utf16_index: usize) -> usize {
+  let mut units = 0;
+  for (i, c) in value.char_indices() {
+    if units >= utf16_index {
+      return i;
+    }
+    units += c.len_utf16();
+  }
+  value.len()
+}
+
+fn selection(textarea: &HtmlTextAreaElement, value: &str) -> (usize, usize) {
+  let start = textarea.selection_start().ok().flatten().map(|s| byte_index(value, s as usize)).unwrap_or(value.len());
+  let end = textarea.selection_end().ok().flatten().map(|s| byte_index(value, s as usize)).unwrap_or(start);
+  (start.min(end), start.max(end))
+}
+
+pub fn cursor(textarea: &HtmlTextAreaElement, value: &str) -> usize {
+  selection(textarea, value).1
+}
+
+// sets the value and selection then fires an input event so drafts are saved as if typed
+fn replace_range(textarea: &HtmlTextAreaElement, value: &str, start: usize, end: usize, insert: &str, select: (usize, usize)) {
+  textarea.set_value(&format!("{}{}{}", &value[..start], insert, &value[end..]));
+  let offset = value[..start].encode_utf16().count();
+  let _ = textarea.set_selection_range(
+    (offset + insert[..select.0].encode_utf16().count()) as u32,
+    (offset + insert[..select.1].encode_utf16().count()) as u32,
+  );
+  let init = EventInit::new();
+  init.set_bubbles(true);
+  if let Ok(e) = web_sys::Event::new_with_event_init_dict("input", &init) {
+    let _ = textarea.dispatch_event(&e);
+  }
+  let _ = textarea.focus();
+}
+
+// replaces `replace_back` bytes before the cursor
+pub fn insert_at_cursor(textarea: &HtmlTextAreaElement, insert: &str, replace_back: usize) {
+  let value = textarea.value();
+  let end = cursor(textarea, &value);
+  replace_range(textarea, &value, end.saturating_sub(replace_back), end, insert, (insert.len(), insert.len()));
+}
+
+fn wrap_selection(textarea: &HtmlTextAreaElement, before: &str, after: &str, placeholder: &str) {
+  let value = textarea.value();
+  let (start, end) = selection(textarea, &value);
+  let inner = if start == end { placeholder } else { &value[start..end] };
+  replace_range(textarea, &value, start, end, &format!("{}{}{}", before, inner, after), (before.len(), before.len() + inner.len()));
+}
+
+fn prefix_lines(textarea: &HtmlTextAreaElement, prefix: &str) {
+  let value = textarea.value();
+  let (start, end) = selection(textarea, &value);
+  let start = value[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+  let lines = value[start..end].split('\n').map(|l| format!("{}{}", prefix, l)).collect::<Vec<_>>().join("\n");
+  replace_range(textarea, &value, start, end, &lines, (lines.len(), lines.len()));
+}
+
+fn apply_format(textarea: &HtmlTextAreaElement, format: Format) {
+  match format {
+    Format::Bold => wrap_selection(textarea, "**", "**", "bold text"),
+    Format::Italic => wrap_selection(textarea, "*", "*", "italic text"),
+    Format::Link => wrap_selection(textarea, "[", "](https://)", "link text"),
+    Format::Quote => prefix_lines(textarea, "> "),
+    Format::Code => {
+      let value = textarea.value();
+      let (start, end) = selection(textarea, &value);
+      if value[start..end].contains('\n') {
+        wrap_selection(textarea, "```\n", "\n```", "")
+      } else {
+        wrap_selection(textarea, "`", "`", "code")
+      }
+    }
+    Format::Spoiler => wrap_selection(textarea, "\n::: spoiler spoiler\n", "\n:::\n", "hidden text"),
+    Format::List => prefix_lines(textarea, "- "),
+  }
+}
+
+#[component]
+pub fn MarkdownEditor(
+  content: RwSignal<String>,
+  textarea: NodeRef<Textarea>,
+  draft_key: Signal<Option<CommentDraftKey>>,
+  #[prop(optional)] draft_post_id: Signal<Option<i32>>,
+  #[prop(optional)] draft_context: Signal<String>,
+  #[prop(optional, into)] on_change: Option<Callback<String>>,
+  #[prop(optional)] participants: Signal<Vec<String>>,
+) -> impl IntoView {
+  let ssr_site = expect_context::<Resource<Result<GetSiteResponse, LemmyAppError>>>();
+  let preview_show = RwSignal::new(false);
+
+  let on_format = move |format: Format| {
+    move |e: MouseEvent| {
+      e.prevent_default();
+      if let Some(t) = textarea.get_untracked() {
+        apply_format(&t, format);
+      }
+    }
+  };
+
+  view! {
+    <div class="flex flex-wrap gap-1 items-center">
+      <div role="tablist" class="tabs tabs-border tabs-sm">
+        <a role="tab" class={move || format!("tab{}", if preview_show.get() { "" } else { " tab-active" })} on:click={move |_| preview_show.set(false)}>
+          "Write"
+        </a>
+        <a role="tab" class={move || format!("tab{}", if preview_show.get() { " tab-active" } else { "" })} on:click={move |_| preview_show.set(true)}>
+          "Preview"
+        </a>
+      </div>
+      <div class={move || format!("flex flex-wrap gap-1{}", if preview_show.get() { " hidden" } else { "" })}>
+        {[Format::Bold, Format::Italic, Format::Link, Format::Quote, Format::Code, Format::Spoiler, Format::List]
+          .into_iter()
+          .map(|f| {
+            view! {
+              <button
+                type="button"
+                class={format!(
+                  "btn btn-ghost btn-xs{}",
+                  match f {
+                    Format::Bold => " font-bold",
+                    Format::Italic => " italic",
+                    Format::Code => " font-mono",
+                    _ => "",
+                  },
+                )}
+                title={f.title()}
+                on:click={on_format(f)}
+              >
+                {f.label()}
+              </button>
+            }
+          })
+          .collect_view()}
+      </div>
+      <span class={move || {
+        format!("ml-auto text-xs{}", if content.get().chars().count() > MAX_COMMENT_LENGTH { " text-error" } else { " text-base-content/50" })
+      }}>{move || format!("{} / {}", content.get().chars().count(), MAX_COMMENT_LENGTH)}</span>
+    </div>
+    <textarea
+      class={move || format!("h-24 w-full text-base textarea textarea-bordered{}", if preview_show.get() { " hidden" } else { "" })}
+      placeholder="Comment text"
+      prop:value={move || content.get()}
+      node_ref={textarea}
+      on:wheel={move |e: WheelEvent| {
+        e.stop_propagation();
+      }}
+      on:keydown={move |e: KeyboardEvent| {
+        if let Some(f) = Format::from_key(&e) {
+          e.prevent_default();
+          if let Some(t) = textarea.get_untracked() {
+            apply_format(&t, f);
+          }
+        }
+      }}
+      on:input={move |ev| {
+        content.set(event_target_value(&ev));
+        if let Some(c) = on_change {
+          c.run(event_target_value(&ev));
+        }
+        #[cfg(not(feature = "ssr"))]
+        if let Some(key) = draft_key.get_untracked() {
+          spawn_local_scoped_with_cancellation(async move {
+            if let Ok(d) = IndexedDb::shared().await {
+              let draft = CommentDraft::new(content.get_untracked(), draft_post_id.get_untracked(), draft_context.get_untracked());
+              if let Ok(_c) = d.set(&key, &draft).await {}
+            }
+          });
+        }
+      }}
+    >
+      {content.get_untracked()}
+    </textarea>
+    <Show when={move || preview_show.get()} fallback={|| {}}>
+      <div
+        class="overflow-y-auto p-2 w-full max-w-none min-h-24 rounded border prose border-base-content/20"
+        on:click={move |e: MouseEvent| {
+          if let Some(t) = e.target() {
+            copy_code(&t);
+          }
+        }}
+        inner_html={move || markdown_to_html(&content.get(), Profile::Body, custom_emojis(ssr_site))}
+      />
+    </Show>
+    <div class={move || if preview_show.get() { "hidden" } else { "" }}>
+      <MentionPicker textarea content={content.into()} participants />
+      <EmojiPicker textarea content={content.into()} />
+    </div>
+  }
+}
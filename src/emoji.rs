@@ -0,0 +1,148 @@
+use crate::{
+  editor::{cursor, insert_at_cursor},
+  errors::LemmyAppError,
+};
+use lemmy_api_common::{lemmy_db_views::structs::CustomEmojiView, site::GetSiteResponse};
+use leptos::{html::Textarea, prelude::*};
+use pulldown_cmark::{Event, Tag, TagEnd};
+use web_sys::MouseEvent;
+
+pub fn custom_emojis(ssr_site: Resource<Result<GetSiteResponse, LemmyAppError>>) -> Vec<CustomEmojiView> {
+  if let Some(Ok(s)) = ssr_site.get() { s.custom_emojis } else { vec![] }
+}
+
+pub fn with_custom_emojis<'a>(events: impl Iterator<Item = Event<'a>>, emojis: Vec<CustomEmojiView>) -> impl Iterator<Item = Event<'a>> {
+  let mut raw_depth = 0usize;
+  events.map(move |event| match event {
+    Event::Start(Tag::CodeBlock(_)) | Event::Start(Tag::Image { .. }) => {
+      raw_depth += 1;
+      event
+    }
+    Event::End(TagEnd::CodeBlock) | Event::End(TagEnd::Image) => {
+      raw_depth = raw_depth.saturating_sub(1);
+      event
+    }
+    Event::Text(text) if raw_depth == 0 && emojis.len() > 0 => {
+      if let Some(html) = replace_shortcodes(&text, &emojis) { Event::InlineHtml(html.into()) } else { Event::Text(text) }
+    }
+    _ => event,
+  })
+}
+
+fn replace_shortcodes(text: &str, emojis: &[CustomEmojiView]) -> Option<String> {
+  let mut html = String::new();
+  let mut rest = text;
+  let mut found = false;
+  while let Some(start) = rest.find(':') {
+    let after = &rest[start + 1..];
+    if let Some(end) = after.find(':') {
+      if let Some(e) = emojis.iter().find(|e| e.custom_emoji.shortcode.eq(&after[..end])) {
+        html.push_str(&html_escape::encode_text(&rest[..start]));
+        html.push_str(&emoji_html(e));
+        rest = &after[end + 1..];
+        found = true;
+        continue;
+      }
+    }
+    html.push_str(&html_escape::encode_text(&rest[..start + 1]));
+    rest = after;
+  }
+  html.push_str(&html_escape::encode_text(rest));
+  if found { Some(html) } else { None }
+}
+
+fn emoji_html(e: &CustomEmojiView) -> String {
+  format!(
+    "<img class=\"emoji\" src=\"{}\" alt=\"{}\" title=\":{}:\" />",
+    html_escape::encode_double_quoted_attribute(&e.custom_emoji.image_url.inner().to_string()),
+    html_escape::encode_double_quoted_attribute(&e.custom_emoji.alt_text),
+    html_escape::encode_double_quoted_attribute(&e.custom_emoji.shortcode),
+  )
+}
+
+#[component]
+pub fn EmojiPicker(textarea: NodeRef<Textarea>, content: Signal<String>) -> impl IntoView {
+  let ssr_site = expect_context::<Resource<Result<GetSiteResponse, LemmyAppError>>>();
+  let picker_show = RwSignal::new(false);
+
+  let partial = Memo::new(move |_| {
+    let value = content.get();
+    let end = textarea.get_untracked().map(|t| cursor(&t, &value)).unwrap_or(value.len());
+    let word = value[..end].rsplit(char::is_whitespace).next().unwrap_or("").to_string();
+    if word.len() > 2 && word.starts_with(':') && !word[1..].contains(':') { Some(word) } else { None }
+  });
+
+  let on_pick = move |shortcode: String, replace_back: usize| {
+    move |e: MouseEvent| {
+      e.prevent_default();
+      if let Some(t) = textarea.get_untracked() {
+        insert_at_cursor(&t, &format!(":{}: ", shortcode), replace_back);
+      }
+      picker_show.set(false);
+    }
+  };
+
+  view! {
+    <Transition fallback={|| {}}>
+      {move || {
+        let emojis = custom_emojis(ssr_site);
+        if emojis.is_empty() {
+          return view! {}.into_any();
+        }
+        let suggestions = if let Some(p) = partial.get() {
+          emojis
+            .iter()
+            .filter(|e| e.custom_emoji.shortcode.starts_with(&p[1..]) || e.keywords.iter().any(|k| k.keyword.starts_with(&p[1..])))
+            .take(8)
+            .map(|e| (e.clone(), p.len()))
+            .collect::<Vec<_>>()
+        } else {
+          vec![]
+        };
+        view! {
+          <div class="flex flex-wrap gap-1 items-center">
+            {suggestions
+              .into_iter()
+              .map(|(e, replace_back)| {
+                view! {
+                  <button
+                    type="button"
+                    class="btn btn-ghost btn-xs"
+                    title={format!(":{}:", e.custom_emoji.shortcode)}
+                    on:click={on_pick(e.custom_emoji.shortcode.clone(), replace_back)}
+                  >
+                    <img class="h-5" src={e.custom_emoji.image_url.inner().to_string()} alt={e.custom_emoji.alt_text.clone()} />
+                    {format!(":{}:", e.custom_emoji.shortcode)}
+                  </button>
+                }
+              })
+              .collect_view()}
+            <button type="button" class="ml-auto btn btn-ghost btn-xs" on:click={move |_| picker_show.update(|b| *b = !*b)} title="Emoji">
+              ":)"
+            </button>
+          </div>
+          <Show when={move || picker_show.get()} fallback={|| {}}>
+            <div class="flex overflow-y-auto flex-wrap gap-1 p-1 max-h-40">
+              {emojis
+                .iter()
+                .map(|e| {
+                  view! {
+                    <button
+                      type="button"
+                      class="btn btn-ghost btn-xs btn-square"
+                      title={format!(":{}: {}", e.custom_emoji.shortcode, e.custom_emoji.category)}
+                      on:click={on_pick(e.custom_emoji.shortcode.clone(), 0)}
+                    >
+                      <img class="h-5" src={e.custom_emoji.image_url.inner().to_string()} alt={e.custom_emoji.alt_text.clone()} />
+                    </button>
+                  }
+                })
+                .collect_view()}
+            </div>
+          </Show>
+        }
+          .into_any()
+      }}
+    </Transition>
+  }
+}
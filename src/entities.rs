@@ -0,0 +1,112 @@
+use crate::errors::LemmyAppResult;
+use lemmy_api_common::{
+  lemmy_db_schema::newtypes::{CommentId, PostId},
+  lemmy_db_views::structs::{CommentView, PostView},
+};
+use leptos::prelude::*;
+use std::{collections::HashMap, hash::Hash};
+
+// one signal per post and comment, shared by every view showing it, so a vote cast in one place shows everywhere.
+// an entry lives only as long as some view holds it, so scrolling an endless feed does not keep every post ever seen
+#[derive(Clone, Copy)]
+pub struct Entities {
+  posts: StoredValue<HashMap<PostId, Held<PostView>>>,
+  comments: StoredValue<HashMap<CommentId, Held<CommentView>>>,
+}
+
+struct Held<T> {
+  signal: ArcRwSignal<T>,
+  holders: usize,
+}
+
+// the shared signal for `id`, seeded with `seen` if nothing holds it yet, and let go when the calling owner is cleaned up
+fn hold<Id, T>(store: StoredValue<HashMap<Id, Held<T>>>, id: Id, seen: T) -> RwSignal<T>
+where
+  Id: Eq + Hash + Clone + Send + Sync + 'static,
+  T: Send + Sync + 'static,
+{
+  let signal = {
+    let mut m = store.write_value();
+    let held = m.entry(id.clone()).or_insert_with(|| Held { signal: ArcRwSignal::new(seen), holders: 0 });
+    held.holders += 1;
+    held.signal.clone()
+  };
+  on_cleanup(move || {
+    store.update_value(|m| {
+      if let Some(held) = m.get_mut(&id) {
+        held.holders -= 1;
+        if held.holders == 0 {
+          m.remove(&id);
+        }
+      }
+    });
+  });
+  signal.into()
+}
+
+// a response straight from the server replaces whatever views are showing. one nothing shows is not kept, whoever
+// shows it next seeds it from the same response
+fn replace<Id: Eq + Hash, T>(store: StoredValue<HashMap<Id, Held<T>>>, id: &Id, value: T) {
+  if let Some(signal) = store.with_value(|m| m.get(id).map(|h| h.signal.clone())) {
+    signal.set(value);
+  }
+}
+
+impl Entities {
+  pub fn new() -> Self {
+    Self { posts: StoredValue::new(HashMap::new()), comments: StoredValue::new(HashMap::new()) }
+  }
+
+  // the shared signal for a post, seeded with `seen` the first time it turns up. anything already stored is newer
+  // than a page rendered from cache, so `seen` does not replace it
+  pub fn post(&self, seen: PostView) -> RwSignal<PostView> {
+    hold(self.posts, seen.post.id, seen)
+  }
+
+  pub fn comment(&self, seen: CommentView) -> RwSignal<CommentView> {
+    hold(self.comments, seen.comment.id, seen)
+  }
+
+  pub fn update_post(&self, post_view: PostView) {
+    let id = post_view.post.id;
+    replace(self.posts, &id, post_view);
+  }
+
+  pub fn update_comment(&self, comment_view: CommentView) {
+    let id = comment_view.comment.id;
+    replace(self.comments, &id, comment_view);
+  }
+
+  pub fn update_posts(&self, posts: &[PostView]) {
+    for p in posts {
+      self.update_post(p.clone());
+    }
+  }
+
+  pub fn update_comments(&self, comments: &[CommentView]) {
+    for c in comments {
+      self.update_comment(c.clone());
+    }
+  }
+}
+
+// hands a successful response to the store before the caller sees it
+pub fn record<T>(result: LemmyAppResult<T>, store: impl FnOnce(Entities, &T)) -> LemmyAppResult<T> {
+  if let Ok(r) = &result
+    && let Some(entities) = use_context::<Entities>()
+  {
+    store(entities, r);
+  }
+  result
+}
+
+// the same for a stale-while-revalidate callback, wrapped where the context is still reachable
+pub fn recording<T: Send + Sync + 'static>(on_fresh: Callback<T>, store: impl Fn(Entities, &T) + Send + Sync + 'static) -> Callback<T> {
+  let entities = use_context::<Entities>();
+  Callback::new(move |r: T| {
+    if let Some(e) = entities {
+      store(e, &r);
+    }
+    on_fresh.run(r);
+  })
+}
@@ -22,6 +22,17 @@ pub enum LemmyAppErrorType {
   InternalClientError,
   ParamsError,
   OfflineError,
+  Queued,
+  // unix millis after which the instance should take requests again
+  RateLimited(i64),
+  // what was entered as an instance is not a host name
+  InvalidInstance,
+  // this deployment does not serve the instance
+  DisallowedInstance,
+  // the host could not be asked what it runs
+  UnreachableInstance,
+  // the software nodeinfo reported instead, empty when it reported none
+  NotLemmyInstance(String),
 
   ApiError(LemmyErrorType),
 
@@ -33,9 +44,24 @@ pub enum LemmyAppErrorType {
 }
 
 pub fn message_from_error(error: &LemmyAppError) -> String {
+  let s = error_message(error);
+
+  leptos::logging::error!("{}\n{:#?}", s, error);
+
+  s
+}
+
+// the message alone, for callers that report errors their own way
+pub fn error_message(error: &LemmyAppError) -> String {
   // let i18n = use_i18n();
 
-  let s = match error.error_type {
+  match error.error_type {
+    LemmyAppErrorType::RateLimited(until) => rate_limit_message(until),
+    LemmyAppErrorType::UnreachableInstance => format!("Could not reach {}", error.content),
+    LemmyAppErrorType::NotLemmyInstance(ref software) if software.len() > 0 => format!("{} runs {}, not Lemmy", error.content, software),
+    LemmyAppErrorType::NotLemmyInstance(_) => format!("{} is not a Lemmy instance", error.content),
+    LemmyAppErrorType::InvalidInstance => format!("{} is not a valid instance address", error.content),
+    LemmyAppErrorType::DisallowedInstance => format!("{} is not available here", error.content),
     // LemmyAppErrorType::ApiError(LemmyErrorType::IncorrectLogin) => t!(i18n, invalid_login)().into_any().to_s,
     // LemmyAppErrorType::EmptyUsername => t!(i18n, empty_username),
     // LemmyAppErrorType::EmptyPassword => t!(i18n, empty_password),
@@ -43,12 +69,14 @@ pub fn message_from_error(error: &LemmyAppError) -> String {
     // LemmyAppErrorType::InternalServerError => t!(i18n, internal),
     // LemmyAppErrorType::Unknown => t!(i18n, unknown),
     LemmyAppErrorType::OfflineError => "App is offline".to_owned(),
+    LemmyAppErrorType::Queued => "Saved offline, will be sent when back online".to_owned(),
     _ => "An error without description".to_owned(),
-  };
-
-  leptos::logging::error!("{}\n{:#?}", s, error);
+  }
+}
 
-  s
+pub fn rate_limit_message(until: i64) -> String {
+  let seconds = (until - jiff::Timestamp::now().as_millisecond() + 999) / 1000;
+  if seconds > 0 { format!("Too many requests, try again in {}s", seconds) } else { "Too many requests, try again now".to_owned() }
 }
 
 #[component]
@@ -76,10 +104,27 @@ pub fn Offline(on_retry_click: Option<impl Fn(MouseEvent) + 'static>) -> impl In
 #[component]
 pub fn Error(error: LemmyAppError, on_retry_click: Option<impl Fn(MouseEvent) + 'static>) -> impl IntoView {
   error!("{:#?}", error);
+  let rate_limited_until = match error.error_type {
+    LemmyAppErrorType::RateLimited(until) => Some(until),
+    _ => None,
+  };
+  let tick = RwSignal::new(0usize);
+  let title = move || {
+    tick.track();
+    rate_limited_until.map(rate_limit_message).unwrap_or("Error".to_owned())
+  };
+  // counts the wait down until the instance takes requests again
+  #[cfg(not(feature = "ssr"))]
+  if let Some(until) = rate_limited_until
+    && until > jiff::Timestamp::now().as_millisecond()
+    && let Ok(handle) = set_interval_with_handle(move || tick.update(|t| *t += 1), std::time::Duration::from_secs(1))
+  {
+    on_cleanup(move || handle.clear());
+  }
   view! {
     <div class="py-4 px-8 break-inside-avoid">
       <div class="flex justify-between alert alert-error alert-soft">
-        <span class="text-lg">{"Error"}</span>
+        <span class="text-lg">{title}</span>
         {if let Some(o) = on_retry_click {
           view! {
             <span on:click={o} class="btn btn-sm">
@@ -0,0 +1,76 @@
+use crate::{
+  client::{Fetch, HttpType, LemmyApi},
+  db::csr_indexed_db::Store,
+  errors::{LemmyAppError, LemmyAppErrorType, LemmyAppResult},
+};
+use leptos::prelude::*;
+use serde::{Serialize, de::DeserializeOwned};
+use std::{collections::HashMap, sync::Arc};
+
+// the instance the recorded responses come from, as far as anything asking is concerned
+pub const FIXTURES_INSTANCE: &str = "fixtures.test";
+
+// recorded responses served in place of the network, keyed by route. provided as a context, or built into the app
+// with the fixtures feature, so components render without a live instance. as a client of its own it answers the
+// whole api the same way
+#[derive(Clone, Default)]
+pub struct Fixtures {
+  responses: Arc<HashMap<String, String>>,
+}
+
+impl Fixtures {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // every request to `route` gets `json` back, whatever its form
+  pub fn with(mut self, route: &str, json: &str) -> Self {
+    Arc::make_mut(&mut self.responses).insert(route.to_owned(), json.to_owned());
+    self
+  }
+
+  // a small instance with one community, two posts, a short thread and the person who wrote them
+  pub fn recorded() -> Self {
+    Self::new()
+      .with("site", include_str!("../fixtures/site.json"))
+      .with("post/list", include_str!("../fixtures/post_list.json"))
+      .with("post", include_str!("../fixtures/post.json"))
+      .with("comment/list", include_str!("../fixtures/comment_list.json"))
+      .with("user", include_str!("../fixtures/user.json"))
+  }
+
+  pub fn serve<Response: DeserializeOwned>(&self, route: &str) -> LemmyAppResult<Response> {
+    match self.responses.get(route) {
+      Some(json) => serde_json::from_str::<Response>(json).map_err(Into::into),
+      None => Err(LemmyAppError { error_type: LemmyAppErrorType::NotFound, content: format!("no fixture for {}", route) }),
+    }
+  }
+}
+
+impl Fetch for Fixtures {
+  fn instance(&self) -> String {
+    FIXTURES_INSTANCE.to_owned()
+  }
+
+  fn jwt(&self) -> Option<String> {
+    None
+  }
+
+  async fn make_request<Response, Form>(&self, _method: HttpType, path: &str, _form: Form) -> LemmyAppResult<Response>
+  where
+    Response: Serialize + DeserializeOwned + 'static + core::fmt::Debug + Send,
+    Form: Serialize + core::clone::Clone + 'static + core::fmt::Debug + Store + Send,
+  {
+    self.serve(path)
+  }
+}
+
+impl LemmyApi for Fixtures {}
+
+// the fixtures requests should be answered from, if any
+pub fn fixtures() -> Option<Fixtures> {
+  #[cfg(feature = "fixtures")]
+  return Some(use_context::<Fixtures>().unwrap_or_else(Fixtures::recorded));
+  #[cfg(not(feature = "fixtures"))]
+  use_context::<Fixtures>()
+}
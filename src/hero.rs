@@ -3,8 +3,12 @@ use crate::{
   client::*,
   comment::Comment,
   db::csr_indexed_db::*,
+  emoji::*,
+  entities::Entities,
   errors::{LemmyAppError, LemmyAppErrorType, LemmyAppResult},
   icon::{Icon, IconType},
+  markdown::*,
+  query_cache::QueryCaches,
   toolbar::PostToolbar,
 };
 use ev::MouseEvent;
@@ -13,6 +17,7 @@ use lemmy_api_common::{
   lemmy_db_schema::{CommentSortType, ListingType, SortType, newtypes::PostId},
   lemmy_db_views::structs::PaginationCursor,
   post::{GetPost, GetPostResponse, GetPosts, GetPostsResponse},
+  site::GetSiteResponse,
 };
 use leptos::{
   html::{Div, Textarea},
@@ -23,7 +28,6 @@ use leptos::{
 use leptos_meta::*;
 use leptos_router::{components::A, hooks::*};
 use leptos_use::{UseIntersectionObserverOptions, use_intersection_observer_with_options};
-use std::collections::BTreeMap;
 use web_sys::{HtmlAnchorElement, HtmlImageElement, wasm_bindgen::JsCast};
 
 #[component]
@@ -37,21 +41,16 @@ pub fn Hero(post_id: Signal<PostId>, hide: bool, #[prop(optional)] next_page_cur
 
   let post_view = RwSignal::new(None::<GetPostResponse>);
 
-  let listing_response_cache = expect_context::<RwSignal<BTreeMap<(usize, GetPosts, Option<String>), (i64, LemmyAppResult<GetPostsResponse>)>>>();
-  let post_response_cache = expect_context::<RwSignal<BTreeMap<(GetPost, Option<String>), (i64, LemmyAppResult<GetPostResponse>)>>>();
-  let comments_response_cache = expect_context::<RwSignal<BTreeMap<(GetComments, Option<String>), (i64, LemmyAppResult<GetCommentsResponse>)>>>();
+  let query_caches = expect_context::<QueryCaches>();
   let ReadAuthCookie(get_auth_cookie) = expect_context::<ReadAuthCookie>();
+  let ssr_site = expect_context::<Resource<Result<GetSiteResponse, LemmyAppError>>>();
 
   let post_resource = Resource::new(
     move || post_id.get(),
     move |id| async move {
       let form = GetPost { id: Some(id), comment_id: None };
-      let rc = post_response_cache.get_untracked();
-      let result = if let Some((_t, r)) = rc.get(&(form.clone(), get_auth_cookie.get_untracked())) {
-        match r {
-          Ok(o) => Ok(o.clone()),
-          _ => LemmyClient.get_post(form.clone()).await,
-        }
+      let result = if let Some((_t, o)) = query_caches.post.get(&(form.clone(), get_auth_cookie.get_untracked())) {
+        Ok(o)
       } else {
         LemmyClient.get_post(form.clone()).await
       };
@@ -80,12 +79,8 @@ pub fn Hero(post_id: Signal<PostId>, hide: bool, #[prop(optional)] next_page_cur
         disliked_only: None,
         liked_only: None,
       };
-      let rc = comments_response_cache.get_untracked();
-      let result = if let Some((_t, r)) = rc.get(&(form.clone(), get_auth_cookie.get_untracked())) {
-        match r {
-          Ok(o) => Ok(o.clone()),
-          _ => LemmyClient.get_comments(form.clone()).await,
-        }
+      let result = if let Some((_t, o)) = query_caches.comments.get(&(form.clone(), get_auth_cookie.get_untracked())) {
+        Ok(o)
       } else {
         LemmyClient.get_comments(form.clone()).await
       };
@@ -171,46 +166,27 @@ pub fn Hero(post_id: Signal<PostId>, hide: bool, #[prop(optional)] next_page_cur
                 let fm = res.0.clone();
                 use crate::db::csr_indexed_db::*;
                 spawn_local_scoped_with_cancellation(async move {
-                  if let Ok(d) = IndexedDb::new().await {
+                  if let Ok(d) = IndexedDb::shared().await {
                     if let Ok(_c) = d.set(&fm, &rw).await {}
                   }
-                  post_response_cache
-                    .update(move |rc| {
-                      rc.insert((fm, get_auth_cookie.get_untracked()), (0, Ok(rw)));
-                    });
+                  query_caches.post.insert((fm, get_auth_cookie.get_untracked()), jiff::Timestamp::now().as_millisecond(), rw);
                 });
               }
               let res2 = res.1.clone();
               let res = res.1.clone();
               post_view.set(Some(res));
-              let post_response = RwSignal::new(res2);
-              let text = if let Some(b) = post_response.get().post_view.post.body.clone() {
-                if b.len() > 0 { Some(b) } else { post_response.get().post_view.post.embed_description.clone() }
-              } else {
-                None
-              };
-              let title = post_response.get().post_view.post.name.clone();
-              let mut options = pulldown_cmark::Options::empty();
-              options.insert(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
-              options.insert(pulldown_cmark::Options::ENABLE_TABLES);
-              options.insert(pulldown_cmark::Options::ENABLE_SUPERSCRIPT);
-              options.insert(pulldown_cmark::Options::ENABLE_SUBSCRIPT);
-              options.insert(pulldown_cmark::Options::ENABLE_CONTAINER_EXTENSIONS);
-              let parser = pulldown_cmark::Parser::new_ext(&title, options);
-              let custom = parser
-                .map(|event| match event {
-                  pulldown_cmark::Event::Html(text) => {
-                    let er = format!("<p>{}</p>", html_escape::encode_safe(&text).to_string());
-                    pulldown_cmark::Event::Html(er.into())
-                  }
-                  pulldown_cmark::Event::InlineHtml(text) => {
-                    let er = html_escape::encode_safe(&text).to_string();
-                    pulldown_cmark::Event::InlineHtml(er.into())
-                  }
-                  _ => event,
-                });
-              let mut title_encoded = String::new();
-              pulldown_cmark::html::push_html(&mut title_encoded, custom);
+              // the post itself is the shared one from the store, so edits and votes made elsewhere show here too
+              let shared_post = expect_context::<Entities>().post(res2.post_view.clone());
+              let post_response = Signal::derive(move || GetPostResponse { post_view: shared_post.get(), ..res2.clone() });
+              let text = Memo::new(move |_| {
+                let post = shared_post.get().post;
+                match post.body {
+                  Some(b) if b.len() > 0 => Some(b),
+                  Some(_) => post.embed_description,
+                  None => None,
+                }
+              });
+              let title_encoded = Memo::new(move |_| markdown_to_html(&shared_post.get().post.name, Profile::Title, custom_emojis(ssr_site)));
               let community_title = if post_response.get().post_view.community.local {
                 format!("{}", post_response.get().post_view.community.name)
               } else {
@@ -333,7 +309,7 @@ pub fn Hero(post_id: Signal<PostId>, hide: bool, #[prop(optional)] next_page_cur
                           e.prevent_default();
                           #[cfg(not(feature = "ssr"))]
                           spawn_local_scoped_with_cancellation(async move {
-                            if let Ok(d) = IndexedDb::new().await {
+                            if let Ok(d) = IndexedDb::shared().await {
                               let _ = d
                                 .set(
                                   &ScrollPositionKey {
@@ -354,40 +330,16 @@ pub fn Hero(post_id: Signal<PostId>, hide: bool, #[prop(optional)] next_page_cur
                                 )
                                 .await;
                             }
-                            listing_response_cache
-                              .update(move |rc| {
-                                rc.remove(
-                                  &(
-                                    0usize,
-                                    GetPosts {
-                                      type_: Some(ListingType::All),
-                                      sort: Some(SortType::Active),
-                                      page: None,
-                                      limit: Some(50),
-                                      community_id: None,
-                                      community_name: Some(
-                                        if post_response.get().post_view.community.local {
-                                          format!("{}", post_response.get().post_view.community.name)
-                                        } else {
-                                          format!(
-                                            "{}@{}",
-                                            post_response.get().post_view.community.name,
-                                            post_response.get().post_view.community.actor_id.inner().host().unwrap().to_string(),
-                                          )
-                                        },
-                                      ),
-                                      saved_only: None,
-                                      liked_only: None,
-                                      disliked_only: None,
-                                      show_hidden: Some(true),
-                                      show_read: Some(true),
-                                      show_nsfw: Some(false),
-                                      page_cursor: None,
-                                    },
-                                    get_auth_cookie.get_untracked(),
-                                  ),
-                                );
-                              });
+                            let community_name = if post_response.get().post_view.community.local {
+                              format!("{}", post_response.get().post_view.community.name)
+                            } else {
+                              format!(
+                                "{}@{}",
+                                post_response.get().post_view.community.name,
+                                post_response.get().post_view.community.actor_id.inner().host().unwrap().to_string(),
+                              )
+                            };
+                            query_caches.listing.invalidate(|(page, f, _), _| *page == 0 && f.community_name.as_ref() == Some(&community_name));
                             next_page_cursor.set((0, None));
                             use_navigate()(
                               &{
@@ -426,38 +378,17 @@ pub fn Hero(post_id: Signal<PostId>, hide: bool, #[prop(optional)] next_page_cur
                   </div>
                 </div>
 
-                {if let Some(ref content) = text {
-                  let mut options = pulldown_cmark::Options::empty();
-                  options.insert(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
-                  options.insert(pulldown_cmark::Options::ENABLE_TABLES);
-                  options.insert(pulldown_cmark::Options::ENABLE_SUPERSCRIPT);
-                  options.insert(pulldown_cmark::Options::ENABLE_SUBSCRIPT);
-                  options.insert(pulldown_cmark::Options::ENABLE_CONTAINER_EXTENSIONS);
-                  options.insert(pulldown_cmark::Options::ENABLE_LINKIFY_LEMMY);
-                  options.insert(pulldown_cmark::Options::ENABLE_LINKIFY_HTTP);
-                  let parser = pulldown_cmark::Parser::new_ext(content, options);
-                  let custom = parser
-                    .map(|event| match event {
-                      pulldown_cmark::Event::Html(text) => {
-                        let er = format!("<p>{}</p>", html_escape::encode_safe(&text).to_string());
-                        pulldown_cmark::Event::Html(er.into())
-                      }
-                      pulldown_cmark::Event::InlineHtml(text) => {
-                        let er = html_escape::encode_safe(&text).to_string();
-                        pulldown_cmark::Event::InlineHtml(er.into())
-                      }
-                      _ => event,
-                    });
-                  let mut safe_html = String::new();
-                  pulldown_cmark::html::push_html(&mut safe_html, custom);
-                  Some(
+                {move || {
+                  text.get().map(|content| {
+                    let safe_html = markdown_to_html(&content, Profile::Body, custom_emojis(ssr_site));
                     view! {
                       <div class="pr-4 pl-4">
                         <div
                           class="py-2"
                           on:click={move |e: MouseEvent| {
                             if let Some(t) = e.target() {
-                              if let Some(i) = t.dyn_ref::<HtmlImageElement>() {
+                              if copy_code(&t) {
+                              } else if let Some(i) = t.dyn_ref::<HtmlImageElement>() {
                                 let _ = window().open_with_url_and_target(&i.src(), "_blank");
                               } else if let Some(l) = t.dyn_ref::<HtmlAnchorElement>() {
                                 e.prevent_default();
@@ -469,10 +400,8 @@ pub fn Hero(post_id: Signal<PostId>, hide: bool, #[prop(optional)] next_page_cur
                           <div class="max-w-none prose" inner_html={safe_html} />
                         </div>
                       </div>
-                    },
-                  )
-                } else {
-                  None
+                    }
+                  })
                 }}
               }
                 .into_any()
@@ -504,13 +433,10 @@ pub fn Hero(post_id: Signal<PostId>, hide: bool, #[prop(optional)] next_page_cur
                 let fm = res.0.clone();
                 use crate::db::csr_indexed_db::*;
                 spawn_local_scoped_with_cancellation(async move {
-                  if let Ok(d) = IndexedDb::new().await {
+                  if let Ok(d) = IndexedDb::shared().await {
                     if let Ok(_c) = d.set(&fm, &rw).await {}
                   }
-                  comments_response_cache
-                    .update(move |rc| {
-                      rc.insert((fm, get_auth_cookie.get_untracked()), (0, Ok(rw)));
-                    });
+                  query_caches.comments.insert((fm, get_auth_cookie.get_untracked()), jiff::Timestamp::now().as_millisecond(), rw);
                 });
               }
               let res = res.1.clone();
@@ -527,7 +453,7 @@ pub fn Hero(post_id: Signal<PostId>, hide: bool, #[prop(optional)] next_page_cur
               #[cfg(not(feature = "ssr"))]
               spawn_local_scoped_with_cancellation(async move {
                 let p = post_id.get();
-                if let Ok(d) = IndexedDb::new().await {
+                if let Ok(d) = IndexedDb::shared().await {
                   if let Ok(Some(mut comment_ids)) = d.get::<i32, Vec<i32>>(&p.0).await {
                     hidden_comments
                       .update(|h| {
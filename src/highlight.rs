@@ -0,0 +1,182 @@
+// small lexical highlighter, output is plain html with hl-* classes so it renders the same under ssr and hydrate
+// and picks up colours from the current theme
+
+struct Language {
+  keywords: &'static [&'static str],
+  line_comments: &'static [&'static str],
+  block_comment: Option<(&'static str, &'static str)>,
+  quotes: &'static [char],
+  ignore_case: bool,
+}
+
+const RUST: Language = Language {
+  keywords: &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop",
+    "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where",
+    "while",
+  ],
+  line_comments: &["//"],
+  block_comment: Some(("/*", "*/")),
+  quotes: &['"'],
+  ignore_case: false,
+};
+
+const C_LIKE: Language = Language {
+  keywords: &[
+    "abstract", "auto", "bool", "break", "case", "catch", "char", "class", "const", "continue", "default", "delete", "do", "double", "else", "enum",
+    "extends", "false", "final", "finally", "float", "for", "goto", "if", "implements", "import", "int", "interface", "long", "namespace", "new", "null",
+    "nullptr", "package", "private", "protected", "public", "return", "short", "signed", "sizeof", "static", "struct", "super", "switch", "template",
+    "this", "throw", "throws", "true", "try", "typedef", "union", "unsigned", "using", "var", "virtual", "void", "volatile", "while",
+  ],
+  line_comments: &["//"],
+  block_comment: Some(("/*", "*/")),
+  quotes: &['"', '\''],
+  ignore_case: false,
+};
+
+const JAVASCRIPT: Language = Language {
+  keywords: &[
+    "as", "async", "await", "break", "case", "catch", "class", "const", "continue", "default", "delete", "do", "else", "enum", "export", "extends", "false",
+    "finally", "for", "from", "function", "if", "implements", "import", "in", "instanceof", "interface", "let", "new", "null", "of", "private", "public",
+    "return", "static", "super", "switch", "this", "throw", "true", "try", "type", "typeof", "undefined", "var", "void", "while", "yield",
+  ],
+  line_comments: &["//"],
+  block_comment: Some(("/*", "*/")),
+  quotes: &['"', '\'', '`'],
+  ignore_case: false,
+};
+
+const GO: Language = Language {
+  keywords: &[
+    "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough", "false", "for", "func", "go", "goto", "if", "import",
+    "interface", "map", "nil", "package", "range", "return", "select", "struct", "switch", "true", "type", "var",
+  ],
+  line_comments: &["//"],
+  block_comment: Some(("/*", "*/")),
+  quotes: &['"', '\'', '`'],
+  ignore_case: false,
+};
+
+const PYTHON: Language = Language {
+  keywords: &[
+    "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else", "except", "False", "finally", "for", "from",
+    "global", "if", "import", "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return", "self", "True", "try", "while", "with",
+    "yield",
+  ],
+  line_comments: &["#"],
+  block_comment: None,
+  quotes: &['"', '\''],
+  ignore_case: false,
+};
+
+const SHELL: Language = Language {
+  keywords: &[
+    "case", "do", "done", "echo", "elif", "else", "esac", "exit", "export", "fi", "for", "function", "if", "in", "local", "return", "then", "until",
+    "while",
+  ],
+  line_comments: &["#"],
+  block_comment: None,
+  quotes: &['"', '\''],
+  ignore_case: false,
+};
+
+const SQL: Language = Language {
+  keywords: &[
+    "and", "as", "asc", "by", "create", "delete", "desc", "distinct", "drop", "from", "group", "having", "in", "index", "insert", "into", "is", "join",
+    "left", "limit", "not", "null", "on", "or", "order", "right", "select", "set", "table", "update", "values", "where", "with",
+  ],
+  line_comments: &["--"],
+  block_comment: Some(("/*", "*/")),
+  quotes: &['\''],
+  ignore_case: true,
+};
+
+const DATA: Language = Language { keywords: &["false", "null", "true"], line_comments: &["#"], block_comment: None, quotes: &['"', '\''], ignore_case: false };
+
+fn language(lang: &str) -> Option<&'static Language> {
+  match lang.to_ascii_lowercase().as_str() {
+    "rust" | "rs" => Some(&RUST),
+    "c" | "h" | "cpp" | "c++" | "cc" | "hpp" | "java" | "cs" | "csharp" | "kotlin" | "kt" | "swift" | "dart" => Some(&C_LIKE),
+    "javascript" | "js" | "jsx" | "typescript" | "ts" | "tsx" | "mjs" => Some(&JAVASCRIPT),
+    "go" | "golang" => Some(&GO),
+    "python" | "py" => Some(&PYTHON),
+    "sh" | "bash" | "shell" | "zsh" | "console" => Some(&SHELL),
+    "sql" | "postgres" | "postgresql" => Some(&SQL),
+    "json" | "yaml" | "yml" | "toml" | "ini" => Some(&DATA),
+    _ => None,
+  }
+}
+
+fn push_span(html: &mut String, class: &str, text: &str) {
+  html.push_str(&format!("<span class=\"hl-{}\">{}</span>", class, html_escape::encode_text(text)));
+}
+
+fn token_end(code: &str, start: usize, lang: &Language) -> (usize, Option<&'static str>) {
+  let rest = &code[start..];
+  if lang.line_comments.iter().any(|c| rest.starts_with(c)) {
+    return (start + rest.find('\n').unwrap_or(rest.len()), Some("comment"));
+  }
+  if let Some((open, close)) = lang.block_comment
+    && rest.starts_with(open)
+  {
+    return (start + open.len() + rest[open.len()..].find(close).map(|i| i + close.len()).unwrap_or(rest.len() - open.len()), Some("comment"));
+  }
+  let mut chars = rest.char_indices();
+  let Some((_, first)) = chars.next() else {
+    return (start, None);
+  };
+  if lang.quotes.contains(&first) {
+    let mut escaped = false;
+    for (i, c) in chars {
+      if escaped {
+        escaped = false;
+      } else if c == '\\' {
+        escaped = true;
+      } else if c == first {
+        return (start + i + c.len_utf8(), Some("string"));
+      } else if c == '\n' && first != '`' {
+        return (start + i, Some("string"));
+      }
+    }
+    return (code.len(), Some("string"));
+  }
+  if first.is_ascii_digit() {
+    let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_')).unwrap_or(rest.len());
+    return (start + end, Some("number"));
+  }
+  if first.is_alphabetic() || first == '_' {
+    let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+    let word = &rest[..end];
+    let class = if lang.keywords.contains(&word) || (lang.ignore_case && lang.keywords.contains(&word.to_ascii_lowercase().as_str())) {
+      Some("keyword")
+    } else if rest[end..].starts_with('(') || rest[end..].starts_with("!(") {
+      Some("function")
+    } else if first.is_uppercase() {
+      Some("type")
+    } else {
+      None
+    };
+    return (start + end, class);
+  }
+  (start + first.len_utf8(), None)
+}
+
+pub fn highlight(code: &str, lang: &str) -> String {
+  let Some(lang) = language(lang) else {
+    return html_escape::encode_text(code).to_string();
+  };
+  let mut html = String::new();
+  let mut plain = 0;
+  let mut i = 0;
+  while i < code.len() {
+    let (end, class) = token_end(code, i, lang);
+    if let Some(class) = class {
+      html.push_str(&html_escape::encode_text(&code[plain..i]));
+      push_span(&mut html, class, &code[i..end]);
+      plain = end;
+    }
+    i = end;
+  }
+  html.push_str(&html_escape::encode_text(&code[plain..]));
+  html
+}
@@ -0,0 +1,255 @@
+use crate::{
+  ReadInstanceCookie,
+  backend::{ApiVersion, remember_version},
+  errors::{LemmyAppError, LemmyAppErrorType, LemmyAppResult, message_from_error},
+  icon::{Icon, IconType::*},
+  server_config::server_config,
+};
+use leptos::{html, prelude::*, server::codee::string::FromToStringCodec, task::spawn_local_scoped_with_cancellation};
+use leptos_use::{SameSite, UseCookieOptions, use_cookie_with_options};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use web_sys::{KeyboardEvent, MouseEvent};
+
+const RECENT_INSTANCES: usize = 6;
+// cookies allow it and hosts never contain it
+const RECENT_SEPARATOR: &str = "|";
+
+// what the picker shows about an instance before switching to it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InstanceInfo {
+  pub host: String,
+  pub name: String,
+  pub icon: Option<String>,
+  pub users: Option<i64>,
+  pub version: String,
+}
+
+// accepts a bare host or a pasted url, keeping only the host and port
+pub fn normalize_host(input: &str) -> Option<String> {
+  let input = input.trim().to_lowercase();
+  let rest = input.strip_prefix("https://").or_else(|| input.strip_prefix("http://")).unwrap_or(&input);
+  let host = rest.split(['/', '?', '#']).next().unwrap_or("").trim_end_matches('.');
+  if host.is_empty() || !host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':')) { None } else { Some(host.to_owned()) }
+}
+
+fn unreachable(host: &str) -> LemmyAppError {
+  LemmyAppError { error_type: LemmyAppErrorType::UnreachableInstance, content: host.to_owned() }
+}
+
+fn not_lemmy(host: &str) -> LemmyAppError {
+  LemmyAppError { error_type: LemmyAppErrorType::NotLemmyInstance("".into()), content: host.to_owned() }
+}
+
+// only a request that never got an answer means the host is unreachable. an error status or something other than
+// json is what most websites answer on fediverse paths
+#[cfg(feature = "ssr")]
+async fn get_json(host: &str, url: &str) -> LemmyAppResult<Value> {
+  let r = crate::client::shared_http()?.get(url).send().await.map_err(|_| unreachable(host))?;
+  if !r.status().is_success() {
+    return Err(not_lemmy(host));
+  }
+  r.json::<Value>().await.map_err(|_| not_lemmy(host))
+}
+
+#[cfg(not(feature = "ssr"))]
+async fn get_json(host: &str, url: &str) -> LemmyAppResult<Value> {
+  let r = gloo_net::http::Request::get(url).send().await.map_err(|_| unreachable(host))?;
+  if !r.ok() {
+    return Err(not_lemmy(host));
+  }
+  r.json::<Value>().await.map_err(|_| not_lemmy(host))
+}
+
+// nodeinfo says what software a host runs without knowing its api, so anything other than lemmy is turned away
+// before its site is asked for
+pub async fn probe_instance(host: &str) -> LemmyAppResult<InstanceInfo> {
+  let index = get_json(host, &format!("https://{}/.well-known/nodeinfo", host)).await?;
+  let href = index["links"].as_array().and_then(|links| links.iter().filter_map(|l| l["href"].as_str()).last()).ok_or_else(|| not_lemmy(host))?;
+  let nodeinfo = get_json(host, href).await?;
+  let software = nodeinfo["software"]["name"].as_str().unwrap_or("").to_lowercase();
+  if software != "lemmy" {
+    return Err(LemmyAppError { error_type: LemmyAppErrorType::NotLemmyInstance(software), content: host.to_owned() });
+  }
+  let version = nodeinfo["software"]["version"].as_str().unwrap_or("").to_owned();
+  let site =
+    get_json(host, &format!("https://{}/{}/site", host, ApiVersion::from_version(&version).prefix())).await.map_err(|_| unreachable(host))?;
+  let site = &site["site_view"]["site"];
+  Ok(InstanceInfo {
+    host: host.to_owned(),
+    name: site["name"].as_str().unwrap_or(host).to_owned(),
+    icon: site["icon"].as_str().map(|i| i.to_owned()),
+    users: nodeinfo["usage"]["users"]["total"].as_i64(),
+    version,
+  })
+}
+
+#[component]
+pub fn InstancePicker(on_switch: Callback<String>) -> impl IntoView {
+  let ReadInstanceCookie(get_instance_cookie) = expect_context::<ReadInstanceCookie>();
+  let (get_recent_cookie, set_recent_cookie) = use_cookie_with_options::<String, FromToStringCodec>(
+    "recent_instances",
+    UseCookieOptions::default().max_age(691200000).path("/").same_site(SameSite::Lax),
+  );
+  // only the hosts are kept, the cookie goes out with every request
+  let recent = Signal::derive(move || {
+    get_recent_cookie
+      .get()
+      .map(|r| r.split(RECENT_SEPARATOR).filter_map(normalize_host).take(RECENT_INSTANCES).collect::<Vec<_>>())
+      .unwrap_or_default()
+  });
+
+  let picker_menu = NodeRef::<html::Details>::new();
+  let host_term = RwSignal::new(String::new());
+  let checking = RwSignal::new(false);
+  let probed: RwSignal<Option<LemmyAppResult<InstanceInfo>>> = RwSignal::new(None);
+
+  let on_check = move |input: String| {
+    host_term.set(input.clone());
+    let Some(host) = normalize_host(&input) else {
+      probed.set(Some(Err(LemmyAppError { error_type: LemmyAppErrorType::InvalidInstance, content: input })));
+      return;
+    };
+    if !server_config().permits(&host) {
+      probed.set(Some(Err(LemmyAppError { error_type: LemmyAppErrorType::DisallowedInstance, content: host })));
+      return;
+    }
+    checking.set(true);
+    probed.set(None);
+    spawn_local_scoped_with_cancellation(async move {
+      probed.set(Some(probe_instance(&host).await));
+      checking.set(false);
+    });
+  };
+
+  let on_switch_click = move |info: InstanceInfo| {
+    move |e: MouseEvent| {
+      e.prevent_default();
+      remember_version(&info.host, ApiVersion::from_version(&info.version));
+      let mut r = recent.get_untracked();
+      r.retain(|h| *h != info.host);
+      r.insert(0, info.host.clone());
+      r.truncate(RECENT_INSTANCES);
+      set_recent_cookie.set(Some(r.join(RECENT_SEPARATOR)));
+      probed.set(None);
+      host_term.set(String::new());
+      if let Some(m) = picker_menu.get() {
+        m.remove_attribute("open");
+      }
+      on_switch.run(info.host.clone());
+    }
+  };
+
+  view! {
+    <li>
+      <details node_ref=picker_menu>
+        <summary title={move || format!("Instance: {}", get_instance_cookie.get().unwrap_or_default())}>
+          <Icon icon={External} />
+        </summary>
+        <ul class="z-[1] w-72 [inset-inline-end:0]">
+          <li>
+            <div class="flex gap-2">
+              <input
+                class="input input-sm"
+                type="text"
+                name="instance"
+                placeholder={move || get_instance_cookie.get().unwrap_or_default()}
+                prop:value={move || host_term.get()}
+                on:input={move |ev| host_term.set(event_target_value(&ev))}
+                on:keypress={move |e: KeyboardEvent| {
+                  if e.key() == "Enter" {
+                    e.prevent_default();
+                    on_check(host_term.get());
+                  }
+                }}
+              />
+              <button type="button" class="btn btn-sm" disabled={move || checking.get()} on:click={move |_| on_check(host_term.get())}>
+                "Check"
+              </button>
+            </div>
+          </li>
+          <Show when={move || checking.get()} fallback={|| {}}>
+            <li>
+              <span class="loading loading-spinner loading-sm" />
+            </li>
+          </Show>
+          {move || match probed.get() {
+            Some(Ok(info)) => {
+              let current = get_instance_cookie.get().as_deref() == Some(info.host.as_str());
+              view! {
+                <li>
+                  <div class="flex gap-3 items-center">
+                    {info.icon.clone().map(|i| view! { <img class="h-8" src={i} /> })}
+                    <div class="flex flex-col">
+                      <span class="font-bold">{info.name.clone()}</span>
+                      <span class="text-xs text-base-content/70">
+                        {format!("{} · Lemmy {}", info.host, info.version)}
+                      </span>
+                      {info.users.map(|u| view! { <span class="text-xs text-base-content/70">{format!("{} users", u)}</span> })}
+                    </div>
+                  </div>
+                </li>
+                <li>
+                  <button type="button" class="btn btn-sm btn-neutral" disabled={current} on:click={on_switch_click(info.clone())}>
+                    {if current { "Current instance" } else { "Switch" }}
+                  </button>
+                </li>
+              }
+                .into_any()
+            }
+            Some(Err(e)) => {
+              view! {
+                <li class="text-sm text-error">
+                  <span>{message_from_error(&e)}</span>
+                </li>
+              }
+                .into_any()
+            }
+            None => view! {}.into_any(),
+          }}
+          <Show when={move || !recent.get().is_empty()} fallback={|| {}}>
+            <li class="menu-title">"Recent"</li>
+          </Show>
+          <For
+            each={move || recent.get()}
+            key={|h| h.clone()}
+            children={move |host: String| {
+              let check = host.clone();
+              view! {
+                <li>
+                  <button type="button" on:click={move |_| on_check(check.clone())}>
+                    {host}
+                  </button>
+                </li>
+              }
+            }}
+          />
+        </ul>
+      </details>
+    </li>
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn hosts_from_input() {
+    for (input, host) in [
+      ("lemmy.world", Some("lemmy.world")),
+      ("  Lemmy.World  ", Some("lemmy.world")),
+      ("https://lemmy.ml/c/rust?page=2", Some("lemmy.ml")),
+      ("http://localhost:8536/", Some("localhost:8536")),
+      ("example.org.", Some("example.org")),
+      ("sub-domain.example.org#top", Some("sub-domain.example.org")),
+      ("", None),
+      ("https://", None),
+      ("exa mple.org", None),
+      ("user@example.org", None),
+      ("{\"host\":1}", None),
+    ] {
+      assert_eq!(normalize_host(input).as_deref(), host, "{}", input);
+    }
+  }
+}
@@ -1,42 +1,60 @@
 #![recursion_limit = "512"]
 #![allow(warnings)]
 
+pub mod backend;
 pub mod client;
 pub mod comment;
 pub mod comments;
 pub mod community;
 pub mod db;
 pub mod default;
+pub mod drafts;
+pub mod editor;
+pub mod emoji;
+pub mod entities;
 pub mod errors;
+pub mod fixtures;
 pub mod hero;
+pub mod highlight;
 pub mod icon;
+pub mod instances;
 pub mod listing;
 pub mod listings;
 pub mod login;
+pub mod markdown;
+pub mod mention;
 pub mod nav;
 pub mod overview;
+pub mod pending;
 pub mod post;
+pub mod query_cache;
+#[cfg(feature = "ssr")]
+pub mod response_cache;
 pub mod root;
 pub mod search;
+pub mod server_config;
+pub mod storage;
 pub mod toolbar;
 pub mod user;
 
 use crate::{
-  client::{LemmyApi, LemmyClient},
-  errors::{LemmyAppError, LemmyAppResult},
+  backend::{ApiVersion, remember_version},
+  client::{Fetch, LemmyApi, LemmyClient},
+  entities::Entities,
+  errors::LemmyAppError,
   login::Login,
+  pending::PendingActions,
   post::Post,
+  query_cache::QueryCaches,
   search::Search,
+  storage::OfflineStorage,
   user::User,
 };
 use codee::string::FromToStringCodec;
 use community::Community;
 use default::Default;
-use lemmy_api_common::{
-  comment::{GetComments, GetCommentsResponse},
-  post::{GetPost, GetPostResponse, GetPosts, GetPostsResponse},
-  site::GetSiteResponse,
-};
+use drafts::Drafts;
+use lemmy_api_common::site::GetSiteResponse;
 use leptos::prelude::*;
 use leptos_meta::{Link, MetaTags, Stylesheet, provide_meta_context, *};
 use leptos_router::{
@@ -48,7 +66,6 @@ use leptos_router::{
 use leptos_use::use_document_visibility;
 use leptos_use::{SameSite, UseCookieOptions, use_cookie_with_options};
 use root::Root;
-use std::collections::BTreeMap;
 
 // leptos_i18n::load_locales!();
 
@@ -137,14 +154,22 @@ pub fn App() -> impl IntoView {
   let _offline_handle = window_event_listener_untyped("offline", on_online(false));
   let _online_handle = window_event_listener_untyped("online", on_online(true));
 
-  let listing_browser_cache: RwSignal<BTreeMap<(usize, GetPosts, Option<String>), (i64, LemmyAppResult<GetPostsResponse>)>> =
-    RwSignal::new(BTreeMap::new());
-  provide_context(listing_browser_cache);
-  let post_browser_cache: RwSignal<BTreeMap<(GetPost, Option<String>), (i64, LemmyAppResult<GetPostResponse>)>> = RwSignal::new(BTreeMap::new());
-  provide_context(post_browser_cache);
-  let comments_browser_cache: RwSignal<BTreeMap<(GetComments, Option<String>), (i64, LemmyAppResult<GetCommentsResponse>)>> =
-    RwSignal::new(BTreeMap::new());
-  provide_context(comments_browser_cache);
+  #[cfg(not(feature = "ssr"))]
+  provide_context(db::csr_indexed_db::SharedIndexedDb::new());
+
+  let pending_actions = PendingActions::new();
+  provide_context(pending_actions);
+  #[cfg(not(feature = "ssr"))]
+  Effect::new(move |_| {
+    if online.get().0 {
+      leptos::task::spawn_local_scoped(pending_actions.replay());
+    } else {
+      leptos::task::spawn_local_scoped(pending_actions.load());
+    }
+  });
+
+  provide_context(QueryCaches::new());
+  provide_context(Entities::new());
 
   let (get_auth_cookie, set_auth_cookie) =
     use_cookie_with_options::<String, FromToStringCodec>("jwt", UseCookieOptions::default().max_age(691200000).path("/").same_site(SameSite::Lax));
@@ -161,12 +186,9 @@ pub fn App() -> impl IntoView {
   );
   provide_context(ReadInstanceCookie(get_instance_cookie));
   provide_context(WriteInstanceCookie(set_instance_cookie));
+  // an instance this deployment does not serve, or a host that is locked to its own, gets the host's instance
   #[cfg(feature = "ssr")]
-  if let Some(t) = get_instance_cookie.get() {
-    set_instance_cookie.set(Some(t));
-  } else {
-    set_instance_cookie.set(Some("lemmy.world".to_owned()));
-  }
+  set_instance_cookie.set(Some(server_config::visitor_instance(get_instance_cookie.get())));
 
   let (get_theme_cookie, set_theme_cookie) =
     use_cookie_with_options::<String, FromToStringCodec>("theme", UseCookieOptions::default().max_age(691200000).path("/").same_site(SameSite::Lax));
@@ -189,6 +211,21 @@ pub fn App() -> impl IntoView {
   );
 
   provide_context(ssr_site);
+  // a hydrated site was fetched by the server, the browser learns which api its instance speaks from it
+  Effect::new(move |_| {
+    if let Some(Ok(site)) = ssr_site.get() {
+      remember_version(&LemmyClient.instance(), ApiVersion::detect(&site));
+    }
+  });
+
+  // the host's branding and the instances it serves are worked out by the server and sent along, the browser has
+  // no config of its own
+  let tenant = server_config::current_tenant();
+  let tenant = Resource::new(move || (), move |()| std::future::ready(tenant.clone()));
+  provide_context(tenant);
+  let allowlist = server_config::Allowlist(server_config::server_config().allowed_instances);
+  provide_context(Resource::new(move || (), move |()| std::future::ready(allowlist.clone())));
+  let brand = move || tenant.get().and_then(|t| t.brand).unwrap_or("AOS".to_owned());
 
   view! {
     <Transition fallback={|| {}}>
@@ -203,9 +240,9 @@ pub fn App() -> impl IntoView {
                     <Title
                       formatter={move |text: String| {
                         if text.len() > 0 {
-                          format!("{} - AOS for {} - {}", text, site.site_view.site.name, d.clone())
+                          format!("{} - {} for {} - {}", text, brand(), site.site_view.site.name, d.clone())
                         } else {
-                          format!("AOS for {} - {}", site.site_view.site.name, d.clone())
+                          format!("{} for {} - {}", brand(), site.site_view.site.name, d.clone())
                         }
                       }}
                       text=""
@@ -216,9 +253,9 @@ pub fn App() -> impl IntoView {
                     <Title
                       formatter={move |text: String| {
                         if text.len() > 0 {
-                          format!("{} - AOS for {}", text, site.site_view.site.name)
+                          format!("{} - {} for {}", text, brand(), site.site_view.site.name)
                         } else {
-                          format!("AOS for {}", site.site_view.site.name)
+                          format!("{} for {}", brand(), site.site_view.site.name)
                         }
                       }}
                       text=""
@@ -227,7 +264,7 @@ pub fn App() -> impl IntoView {
                 }
               }
               _ => {
-                view! { <Title formatter={|text: String| if text.len() > 0 { format!("{} - AOS", text) } else { format!("AOS") }} text="" /> }
+                view! { <Title formatter={move |text: String| if text.len() > 0 { format!("{} - {}", text, brand()) } else { brand() }} text="" /> }
               }
             }
           })
@@ -242,9 +279,12 @@ pub fn App() -> impl IntoView {
           <Route path={StaticSegment("")} view={Default} />
           <Route path={StaticSegment("l")} view={Login} />
           <Route path={(StaticSegment("p"), ParamSegment("id"))} view={Post} />
+          <Route path={(StaticSegment("p"), ParamSegment("id"), StaticSegment("c"), ParamSegment("comment_id"))} view={Post} />
           <Route path={(StaticSegment("c"), ParamSegment("name"))} view={Community} />
           <Route path={(StaticSegment("u"), ParamSegment("name"))} view={User} />
           <Route path={StaticSegment("s")} view={Search} />
+          <Route path={StaticSegment("d")} view={Drafts} />
+          <Route path={StaticSegment("o")} view={OfflineStorage} />
         </ParentRoute>
       </Routes>
     </Router>
@@ -1,8 +1,12 @@
 use crate::{
-  OnlineSetter, ReadInstanceCookie,
+  ReadInstanceCookie,
   client::*,
+  emoji::*,
+  entities::Entities,
   errors::{Error, LemmyAppError, LemmyAppErrorType, Loading},
   icon::{IconType::*, *},
+  markdown::*,
+  pending::is_queued,
 };
 use lemmy_api_common::{lemmy_db_views::structs::*, person::*, post::*, site::GetSiteResponse};
 use leptos::{html::Img, logging::*, prelude::*};
@@ -94,9 +98,8 @@ pub async fn report_post_fn(post_id: i32, reason: String) -> Result<Option<PostR
 #[component]
 pub fn Listing(post_view: PostView, post_number: usize, /*reply_show: RwSignal<bool>, */ hide: bool) -> impl IntoView {
   let ssr_site = expect_context::<Resource<Result<GetSiteResponse, LemmyAppError>>>();
-  let online = expect_context::<RwSignal<OnlineSetter>>();
   let ReadInstanceCookie(get_instance_cookie) = expect_context::<ReadInstanceCookie>();
-  let post_view = RwSignal::new(post_view);
+  let post_view = expect_context::<Entities>().post(post_view);
   let loading = RwSignal::new(false);
   let error = RwSignal::new(false);
   let latest_error = RwSignal::new(LemmyAppError { error_type: LemmyAppErrorType::Unknown, content: "".to_owned() });
@@ -115,6 +118,9 @@ pub fn Listing(post_view: PostView, post_number: usize, /*reply_show: RwSignal<b
           Ok(o) => {
             post_view.set(o.post_view);
           }
+          Err(_) if is_queued(&result) => {
+            post_view.update(|pv| pv.my_vote = Some(score));
+          }
           Err(e) => {
             error.set(true);
             latest_error.set(e);
@@ -226,28 +232,7 @@ pub fn Listing(post_view: PostView, post_number: usize, /*reply_show: RwSignal<b
   };
 
   let title = post_view.get().post.name.clone();
-  let mut options = pulldown_cmark::Options::empty();
-  options.insert(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
-  options.insert(pulldown_cmark::Options::ENABLE_TABLES);
-  options.insert(pulldown_cmark::Options::ENABLE_SUPERSCRIPT);
-  options.insert(pulldown_cmark::Options::ENABLE_SUBSCRIPT);
-  options.insert(pulldown_cmark::Options::ENABLE_CONTAINER_EXTENSIONS);
-  let parser = pulldown_cmark::Parser::new_ext(&title, options);
-
-  let custom = parser.map(|event| match event {
-    pulldown_cmark::Event::Html(text) => {
-      let er = format!("<p>{}</p>", html_escape::encode_safe(&text).to_string());
-      pulldown_cmark::Event::Html(er.into())
-    }
-    pulldown_cmark::Event::InlineHtml(text) => {
-      let er = html_escape::encode_safe(&text).to_string();
-      pulldown_cmark::Event::InlineHtml(er.into())
-    }
-    _ => event,
-  });
-  let mut title_encoded_mut = String::new();
-  pulldown_cmark::html::push_html(&mut title_encoded_mut, custom);
-  let title_encoded = Memo::new(move |_| title_encoded_mut.clone());
+  let title_encoded = Memo::new(move |_| markdown_to_html(&title, Profile::Title, custom_emojis(ssr_site)));
 
   let community_title = if post_view.get().community.local {
     format!("{}", post_view.get().community.name)
@@ -371,7 +356,7 @@ pub fn Listing(post_view: PostView, post_number: usize, /*reply_show: RwSignal<b
                     )
                   }}>
                     <A href={move || format!("/p/{}", post_view.get_untracked().post.id)} attr:class="block hover:text-accent">
-                      <span class="overflow-y-auto text-lg wrap-anywhere" inner_html={title_encoded.get_untracked()} />
+                      <span class="overflow-y-auto text-lg wrap-anywhere" inner_html={title_encoded} />
                     </A>
                     <span class="block mt-1 mb-1 text-sm wrap-anywhere">
                       <span>{abbr_duration}</span>
@@ -420,10 +405,10 @@ pub fn Listing(post_view: PostView, post_number: usize, /*reply_show: RwSignal<b
                             format!(
                               "{}{}",
                               { if Some(1) == post_view.get().my_vote { "text-secondary" } else { "" } },
-                              { if !logged_in.get() || !online.get().0 { " text-base-content/50" } else { " hover:text-secondary/50" } },
+                              { if !logged_in.get() { " text-base-content/50" } else { " hover:text-secondary/50" } },
                             )
                           }}
-                          disabled={move || !logged_in.get() || !online.get().0}
+                          disabled={move || !logged_in.get()}
                           title="Up vote"
                         >
                           <Icon icon={Upvote} />
@@ -5,13 +5,26 @@
 // #[tokio::main]
 // async
 fn main() {
-  let stack_size_bytes = 8 * 1024 * 1024;
+  use aos::server_config::{ServerConfig, set_server_config};
+
+  let config = ServerConfig::load().unwrap_or_else(|e| {
+    eprintln!("server config: {}", e);
+    std::process::exit(1);
+  });
   let runtime = tokio::runtime::Builder::new_multi_thread()
-    .worker_threads(4)
-    .thread_stack_size(stack_size_bytes)
+    .worker_threads(config.worker_threads)
+    .thread_stack_size(config.thread_stack_size)
     .enable_all()
     .build()
     .expect("failed to build Tokio runtime with custom stack size");
+  set_server_config(config);
+
+  if let Err(e) = aos::client::configure_http(&aos::client::HttpConfig::from_env()) {
+    leptos::logging::log!("{}", e.content);
+  }
+  if let Err(e) = aos::response_cache::configure_cache(aos::response_cache::CacheConfig::from_env()) {
+    leptos::logging::log!("{}", e.content);
+  }
 
   runtime.block_on(async {
     use aos::{App, html_template};
@@ -25,10 +38,23 @@ fn main() {
       let app_routes = generate_route_list(App);
 
       let service_router = Router::new()
-        .leptos_routes(&leptos_options, app_routes, {
-          let leptos_options = leptos_options.clone();
-          move || html_template(leptos_options.clone())
-        })
+        .leptos_routes_with_context(
+          &leptos_options,
+          app_routes,
+          // the Host header picks the instance and branding a page is rendered with
+          || {
+            use aos::server_config::server_config;
+            use leptos::prelude::{provide_context, use_context};
+            if let Some(parts) = use_context::<http::request::Parts>() {
+              let host = parts.headers.get(http::header::HOST).and_then(|h| h.to_str().ok());
+              provide_context(server_config().tenant_for(host));
+            }
+          },
+          {
+            let leptos_options = leptos_options.clone();
+            move || html_template(leptos_options.clone())
+          },
+        )
         .fallback(leptos_axum::file_and_error_handler(html_template))
         .with_state(leptos_options);
 
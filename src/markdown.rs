@@ -0,0 +1,308 @@
+use crate::{emoji::with_custom_emojis, highlight::highlight};
+use lemmy_api_common::lemmy_db_views::structs::CustomEmojiView;
+use leptos::prelude::window;
+use pulldown_cmark::{CodeBlockKind, Event, LinkType, Options, Parser, Tag, TagEnd};
+use web_sys::{Element, EventTarget, wasm_bindgen::JsCast};
+
+const URL_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Profile {
+  Title,
+  Body,
+  Bio,
+  Sidebar,
+}
+
+impl Profile {
+  fn options(self) -> Options {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_SUPERSCRIPT);
+    options.insert(Options::ENABLE_SUBSCRIPT);
+    match self {
+      Profile::Title => {}
+      Profile::Bio => {
+        options.insert(Options::ENABLE_CONTAINER_EXTENSIONS);
+        options.insert(Options::ENABLE_LINKIFY_LEMMY);
+        options.insert(Options::ENABLE_LINKIFY_HTTP);
+      }
+      Profile::Body | Profile::Sidebar => {
+        options.insert(Options::ENABLE_TABLES);
+        options.insert(Options::ENABLE_CONTAINER_EXTENSIONS);
+        options.insert(Options::ENABLE_LINKIFY_LEMMY);
+        options.insert(Options::ENABLE_LINKIFY_HTTP);
+      }
+    }
+    options
+  }
+
+  // titles sit inside clickable headers so nested anchors and images are flattened to text
+  fn links_and_images(self) -> bool {
+    self != Profile::Title
+  }
+}
+
+// relative urls have no scheme and are always allowed, browsers ignore control characters and whitespace when reading a scheme
+pub fn safe_url(url: &str) -> bool {
+  let url = url.chars().filter(|c| !c.is_ascii_control() && !c.is_whitespace()).collect::<String>();
+  match url.find([':', '/', '?', '#']) {
+    Some(i) if url[i..].starts_with(':') => URL_SCHEMES.iter().any(|s| url[..i].eq_ignore_ascii_case(s)),
+    _ => true,
+  }
+}
+
+fn external_url(url: &str) -> bool {
+  let url = url.trim_start().to_ascii_lowercase();
+  url.starts_with("http://") || url.starts_with("https://") || url.starts_with("//")
+}
+
+fn code_block_html(lang: &str, code: &str) -> String {
+  let lang = lang.chars().take_while(|c| c.is_ascii_alphanumeric() || *c == '+' || *c == '-' || *c == '#').collect::<String>();
+  format!(
+    "<div class=\"code-block\"><button type=\"button\" class=\"code-copy\" title=\"Copy code\">Copy</button><pre><code{}>{}</code></pre></div>",
+    if lang.is_empty() { String::new() } else { format!(" class=\"language-{}\"", lang) },
+    highlight(code, &lang),
+  )
+}
+
+// handles clicks on the copy buttons rendered into code blocks, returns true when the click was one
+pub fn copy_code(target: &EventTarget) -> bool {
+  let Some(button) = target.dyn_ref::<Element>().and_then(|e| e.closest(".code-copy").ok().flatten()) else {
+    return false;
+  };
+  if let Some(code) = button.parent_element().and_then(|p| p.query_selector("pre").ok().flatten()).and_then(|p| p.text_content()) {
+    let _ = window().navigator().clipboard().write_text(&code);
+    button.set_text_content(Some("Copied"));
+  }
+  true
+}
+
+fn sanitize<'a>(events: impl Iterator<Item = Event<'a>>, profile: Profile) -> impl Iterator<Item = Event<'a>> {
+  // whether each open link or image was kept, so its end event can be matched up
+  let mut links: Vec<bool> = vec![];
+  let mut images: Vec<bool> = vec![];
+  // code blocks are collected whole and highlighted in one go
+  let mut code: Option<(String, String)> = None;
+  events.filter_map(move |event| match event {
+    Event::Start(Tag::CodeBlock(kind)) => {
+      let lang = match kind {
+        CodeBlockKind::Fenced(info) => info.split(|c: char| c.is_whitespace() || c == ',').next().unwrap_or("").to_string(),
+        CodeBlockKind::Indented => String::new(),
+      };
+      code = Some((lang, String::new()));
+      None
+    }
+    Event::Text(text) if code.is_some() => {
+      if let Some((_, c)) = code.as_mut() {
+        c.push_str(&text);
+      }
+      None
+    }
+    Event::End(TagEnd::CodeBlock) => code.take().map(|(lang, c)| Event::Html(code_block_html(&lang, &c).into())),
+    Event::Html(text) => Some(Event::Html(format!("<p>{}</p>", html_escape::encode_safe(&text)).into())),
+    Event::InlineHtml(text) => Some(Event::InlineHtml(html_escape::encode_safe(&text).to_string().into())),
+    Event::Start(Tag::Link { link_type, dest_url, title, .. }) => {
+      let href = if link_type == LinkType::Email { format!("mailto:{}", dest_url) } else { dest_url.to_string() };
+      let keep = profile.links_and_images() && safe_url(&href);
+      links.push(keep);
+      if keep {
+        Some(Event::InlineHtml(
+          format!(
+            "<a href=\"{}\"{}{}>",
+            html_escape::encode_double_quoted_attribute(&href),
+            if title.is_empty() { String::new() } else { format!(" title=\"{}\"", html_escape::encode_double_quoted_attribute(&title)) },
+            if external_url(&href) { " rel=\"nofollow noopener\"" } else { "" },
+          )
+          .into(),
+        ))
+      } else {
+        None
+      }
+    }
+    Event::End(TagEnd::Link) => {
+      if links.pop().unwrap_or(false) {
+        Some(Event::InlineHtml("</a>".into()))
+      } else {
+        None
+      }
+    }
+    Event::Start(Tag::Image { ref dest_url, .. }) => {
+      let keep = profile.links_and_images() && safe_url(dest_url);
+      images.push(keep);
+      if keep { Some(event) } else { None }
+    }
+    Event::End(TagEnd::Image) => {
+      if images.pop().unwrap_or(false) {
+        Some(event)
+      } else {
+        None
+      }
+    }
+    _ => Some(event),
+  })
+}
+
+pub fn markdown_to_html(content: &str, profile: Profile, emojis: Vec<CustomEmojiView>) -> String {
+  let parser = Parser::new_ext(content, profile.options());
+  let mut html = String::new();
+  pulldown_cmark::html::push_html(&mut html, with_custom_emojis(sanitize(parser, profile), emojis));
+  html
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn body(content: &str) -> String {
+    markdown_to_html(content, Profile::Body, vec![])
+  }
+
+  #[test]
+  fn url_schemes() {
+    assert!(safe_url("https://example.com/a?b=c#d"));
+    assert!(safe_url("HTTP://example.com"));
+    assert!(safe_url("mailto:someone@example.com"));
+    assert!(safe_url("/c/rust@lemmy.world"));
+    assert!(safe_url("#heading"));
+    assert!(safe_url("page?next=javascript:alert(1)"));
+    assert!(!safe_url("javascript:alert(1)"));
+    assert!(!safe_url("JaVaScRiPt:alert(1)"));
+    assert!(!safe_url(" java\tscript:alert(1)"));
+    assert!(!safe_url("java\nscript:alert(1)"));
+    assert!(!safe_url("vbscript:msgbox(1)"));
+    assert!(!safe_url("data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg=="));
+  }
+
+  #[test]
+  fn block_html_is_escaped() {
+    let html = body("<script>alert(1)</script>");
+    assert!(!html.contains("<script"));
+    assert!(html.contains("&lt;script&gt;"));
+  }
+
+  #[test]
+  fn inline_html_is_escaped() {
+    let html = body("hello <img src=x onerror=alert(1)> world");
+    assert!(!html.contains("<img"));
+    assert!(html.contains("&lt;img"));
+  }
+
+  #[test]
+  fn javascript_links_are_dropped() {
+    for md in
+      ["[click](javascript:alert(1))", "[click](JAVASCRIPT:alert(1))", "[click](&#106;avascript:alert(1))", "[click](<java&#x09;script:alert(1)>)"]
+    {
+      let html = body(md);
+      assert!(!html.contains("<a"), "{md} rendered {html}");
+      assert!(!html.to_lowercase().contains("javascript"), "{md} rendered {html}");
+      assert!(html.contains("click"));
+    }
+  }
+
+  #[test]
+  fn reference_links_are_checked() {
+    let html = body("[click][x]\n\n[x]: javascript:alert(1)");
+    assert!(!html.contains("<a"));
+    assert!(html.contains("click"));
+  }
+
+  #[test]
+  fn unsafe_images_become_alt_text() {
+    let html = body("![alt text](data:image/svg+xml;base64,PHN2Zz4=)");
+    assert!(!html.contains("<img"));
+    assert!(html.contains("alt text"));
+    let html = body("![alt text](javascript:alert(1))");
+    assert!(!html.contains("<img"));
+  }
+
+  #[test]
+  fn safe_images_are_kept() {
+    let html = body("![cat](https://example.com/cat.png)");
+    assert!(html.contains("<img src=\"https://example.com/cat.png\" alt=\"cat\""));
+  }
+
+  #[test]
+  fn external_links_get_rel() {
+    let html = body("[site](https://example.com)");
+    assert!(html.contains("<a href=\"https://example.com\" rel=\"nofollow noopener\">site</a>"));
+    let html = body("<https://example.com>");
+    assert!(html.contains("rel=\"nofollow noopener\""));
+  }
+
+  #[test]
+  fn internal_links_have_no_rel() {
+    let html = body("[rust](/c/rust)");
+    assert!(html.contains("<a href=\"/c/rust\">rust</a>"));
+  }
+
+  #[test]
+  fn email_autolinks_use_mailto() {
+    let html = body("<someone@example.com>");
+    assert!(html.contains("href=\"mailto:someone@example.com\""));
+  }
+
+  #[test]
+  fn attributes_cannot_be_broken_out_of() {
+    let html = body("[x](https://example.com/\"onmouseover=alert(1) \"a\\\"title\")");
+    assert!(!html.contains("\"onmouseover"));
+    assert!(!html.contains("\"title"));
+  }
+
+  #[test]
+  fn code_is_escaped() {
+    let html = body("`<b>bold</b>`\n\n```\n<script>alert(1)</script>\n```");
+    assert!(!html.contains("<b>"));
+    assert!(!html.contains("<script"));
+  }
+
+  #[test]
+  fn code_blocks_are_highlighted() {
+    let html = body("```rust\nfn main() { let s = \"<b>\"; } // done\n```");
+    assert!(html.contains("<code class=\"language-rust\">"));
+    assert!(html.contains("<span class=\"hl-keyword\">fn</span> <span class=\"hl-function\">main</span>"));
+    assert!(html.contains("<span class=\"hl-string\">\"&lt;b&gt;\"</span>"));
+    assert!(html.contains("<span class=\"hl-comment\">// done</span>"));
+    assert!(html.contains("class=\"code-copy\""));
+  }
+
+  #[test]
+  fn unknown_code_languages_are_plain() {
+    let html = body("```klingon\n<script>alert(1)</script>\n```");
+    assert!(!html.contains("<script"));
+    assert!(!html.contains("hl-"));
+    let html = body("```\" onmouseover=\"alert(1)\nx\n```");
+    assert!(!html.contains("onmouseover"));
+  }
+
+  #[test]
+  fn body_extensions() {
+    assert!(body("~~gone~~").contains("<del>gone</del>"));
+    assert!(body("| a | b |\n|---|---|\n| 1 | 2 |").contains("<table>"));
+    assert!(body("**bold** _it_").contains("<strong>bold</strong> <em>it</em>"));
+  }
+
+  #[test]
+  fn titles_flatten_links_and_images() {
+    let html = markdown_to_html("a [link](https://example.com) and ![img](https://example.com/i.png)", Profile::Title, vec![]);
+    assert!(!html.contains("<a"));
+    assert!(!html.contains("<img"));
+    assert!(html.contains("link"));
+    assert!(html.contains("img"));
+  }
+
+  #[test]
+  fn titles_skip_tables() {
+    let html = markdown_to_html("| a | b |\n|---|---|\n| 1 | 2 |", Profile::Title, vec![]);
+    assert!(!html.contains("<table>"));
+  }
+
+  #[test]
+  fn bio_and_sidebar_are_sanitized() {
+    for profile in [Profile::Bio, Profile::Sidebar] {
+      let html = markdown_to_html("<iframe src=x></iframe> [x](javascript:alert(1))", profile, vec![]);
+      assert!(!html.contains("<iframe"));
+      assert!(!html.contains("<a"));
+    }
+  }
+}
@@ -0,0 +1,118 @@
+use crate::{
+  client::*,
+  editor::{cursor, insert_at_cursor},
+};
+use lemmy_api_common::{
+  lemmy_db_schema::{SearchType, source::person::Person},
+  lemmy_db_views::structs::CommentView,
+  site::Search,
+};
+use leptos::{html::Textarea, prelude::*};
+use leptos_use::signal_debounced;
+use web_sys::MouseEvent;
+
+pub fn person_handle(person: &Person) -> String {
+  format!("@{}@{}", person.name, person.actor_id.inner().host_str().unwrap_or(""))
+}
+
+// thread participants, most recent commenter first
+pub fn participants(comments: &[CommentView]) -> Vec<String> {
+  let mut comments = comments.iter().collect::<Vec<_>>();
+  comments.sort_by(|a, b| b.comment.published.cmp(&a.comment.published));
+  let mut handles: Vec<String> = vec![];
+  for cv in comments {
+    let handle = person_handle(&cv.creator);
+    if !handles.contains(&handle) {
+      handles.push(handle);
+    }
+  }
+  handles
+}
+
+#[component]
+pub fn MentionPicker(textarea: NodeRef<Textarea>, content: Signal<String>, participants: Signal<Vec<String>>) -> impl IntoView {
+  let partial = Memo::new(move |_| {
+    let value = content.get();
+    let end = textarea.get_untracked().map(|t| cursor(&t, &value)).unwrap_or(value.len());
+    let word = value[..end].rsplit(char::is_whitespace).next().unwrap_or("").to_string();
+    if word.len() > 2 && (word.starts_with('@') || word.starts_with('!')) { Some(word) } else { None }
+  });
+  let debounced: Signal<Option<String>> = signal_debounced(partial, 300.0);
+
+  let search_resource = Resource::new(
+    move || debounced.get(),
+    move |word| async move {
+      let Some(word) = word else {
+        return vec![];
+      };
+      let users = word.starts_with('@');
+      let form = Search {
+        q: word[1..].split('@').next().unwrap_or("").to_string(),
+        type_: Some(if users { SearchType::Users } else { SearchType::Communities }),
+        sort: None,
+        community_name: None,
+        community_id: None,
+        page: None,
+        limit: Some(8),
+        creator_id: None,
+        listing_type: None,
+        post_title_only: None,
+      };
+      match LemmyClient.search(form).await {
+        Ok(o) if users => o.users.iter().map(|u| person_handle(&u.person)).collect::<Vec<_>>(),
+        Ok(o) => o
+          .communities
+          .iter()
+          .map(|c| format!("!{}@{}", c.community.name, c.community.actor_id.inner().host_str().unwrap_or("")))
+          .collect::<Vec<_>>(),
+        Err(_) => vec![],
+      }
+    },
+  );
+
+  let on_pick = move |handle: String, replace_back: usize| {
+    move |e: MouseEvent| {
+      e.prevent_default();
+      if let Some(t) = textarea.get_untracked() {
+        insert_at_cursor(&t, &format!("{} ", handle), replace_back);
+      }
+    }
+  };
+
+  view! {
+    <Transition fallback={|| {}}>
+      {move || {
+        let Some(p) = partial.get() else {
+          return view! {}.into_any();
+        };
+        let typed = p.to_lowercase();
+        let mut suggestions = if p.starts_with('@') {
+          participants.get().into_iter().filter(|h| h.to_lowercase().starts_with(&typed)).collect::<Vec<_>>()
+        } else {
+          vec![]
+        };
+        for h in search_resource.get().unwrap_or_default() {
+          if !suggestions.contains(&h) {
+            suggestions.push(h);
+          }
+        }
+        suggestions.truncate(8);
+        view! {
+          <div class="flex flex-wrap gap-1 items-center">
+            {suggestions
+              .into_iter()
+              .map(|h| {
+                view! {
+                  <button type="button" class="btn btn-ghost btn-xs" on:click={on_pick(h.clone(), p.len())}>
+                    {h.clone()}
+                  </button>
+                }
+              })
+              .collect_view()}
+          </div>
+        }
+          .into_any()
+      }}
+    </Transition>
+  }
+}
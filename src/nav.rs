@@ -4,6 +4,10 @@ use crate::{
   db::csr_indexed_db::*,
   errors::{LemmyAppError, LemmyAppResult},
   icon::{IconType::*, *},
+  instances::InstancePicker,
+  pending::PendingMenu,
+  query_cache::QueryCaches,
+  server_config::Tenant,
 };
 use lemmy_api_common::{
   lemmy_db_schema::{ListingType, SortType},
@@ -14,7 +18,6 @@ use lemmy_api_common::{
 use leptos::{logging::log, html::Div, prelude::*, server::codee::string::FromToStringCodec, task::spawn_local_scoped_with_cancellation, *};
 use leptos_router::{components::*, hooks::*, *};
 use leptos_use::{SameSite, UseCookieOptions, use_cookie_with_options};
-use std::collections::BTreeMap;
 
 use web_sys::{KeyboardEvent, MouseEvent, SubmitEvent, VisibilityState};
 
@@ -54,11 +57,7 @@ pub async fn search_fn(term: String) -> Result<(), ServerFnError> {
 #[server]
 pub async fn instance_fn(instance: String) -> Result<(), ServerFnError> {
   let WriteInstanceCookie(set_instance_cookie) = expect_context::<WriteInstanceCookie>();
-  if instance.len() > 0 {
-    set_instance_cookie.set(Some(instance));
-  } else {
-    set_instance_cookie.set(Some("lemmy.world".to_owned()));
-  }
+  set_instance_cookie.set(Some(crate::server_config::visitor_instance(Some(instance))));
   Ok(())
 }
 
@@ -89,11 +88,12 @@ pub fn TopNav(
 ) -> impl IntoView {
   // let i18n = use_i18n();
   let ssr_site = expect_context::<Resource<Result<GetSiteResponse, LemmyAppError>>>();
+  let tenant = expect_context::<Resource<Tenant>>();
 
   let ReadAuthCookie(get_auth_cookie) = expect_context::<ReadAuthCookie>();
   let WriteThemeCookie(set_theme_cookie) = expect_context::<WriteThemeCookie>();
   let _online = expect_context::<RwSignal<OnlineSetter>>();
-  let response_cache = expect_context::<RwSignal<BTreeMap<(usize, GetPosts, Option<String>), (i64, LemmyAppResult<GetPostsResponse>)>>>();
+  let query_caches = expect_context::<QueryCaches>();
 
   let query = use_query_map();
   let _ssr_query_error =
@@ -110,7 +110,6 @@ pub fn TopNav(
   let sm_content_menu = NodeRef::<html::Details>::new();
   let sm_ui_menu = NodeRef::<html::Details>::new();
 
-  let instance_input = NodeRef::<html::Input>::new();
   let search_input = NodeRef::<html::Input>::new();
 
   #[derive(PartialEq)]
@@ -168,27 +167,8 @@ pub fn TopNav(
   let on_sort_click = move |s: SortType| {
     move |_e: MouseEvent| {
       let o = serde_json::to_string::<SortType>(&s).unwrap_or("Active".into());
-      response_cache.update(move |rc| {
-        rc.remove(&(
-          0usize,
-          GetPosts {
-            type_: Some(ssr_list()),
-            sort: Some(s),
-            page: None,
-            limit: Some(50),
-            community_id: None,
-            community_name: None,
-            saved_only: None,
-            liked_only: None,
-            disliked_only: None,
-            show_hidden: Some(true),
-            show_read: Some(true),
-            show_nsfw: Some(false),
-            page_cursor: None,
-          },
-          get_auth_cookie.get_untracked(),
-        ));
-      });
+      let list = ssr_list();
+      query_caches.listing.invalidate(|(page, f, _), _| *page == 0 && f.type_ == Some(list) && f.sort == Some(s));
       let mut query_params = query.get();
       query_params.remove("sort");
       query_params.remove("page");
@@ -198,7 +178,7 @@ pub fn TopNav(
       let params = query_params.clone();
       #[cfg(not(feature = "ssr"))]
       spawn_local_scoped_with_cancellation(async move {
-        if let Ok(d) = IndexedDb::new().await {
+        if let Ok(d) = IndexedDb::shared().await {
           let _ = d.set(&ScrollPositionKey { path: use_location().pathname.get(), query: params.to_query_string() }, &0i32).await;
         }
         use_navigate()(&format!("{}{}", use_location().pathname.get(), query_params.to_query_string()), Default::default());
@@ -214,27 +194,8 @@ pub fn TopNav(
 
   let on_csr_filter_click = move |l: ListingType| {
     move |_e: MouseEvent| {
-      response_cache.update(move |rc| {
-        rc.remove(&(
-          0usize,
-          GetPosts {
-            type_: Some(l),
-            sort: Some(ssr_sort()),
-            page: None,
-            limit: Some(50),
-            community_id: None,
-            community_name: None,
-            saved_only: None,
-            liked_only: None,
-            disliked_only: None,
-            show_hidden: Some(true),
-            show_read: Some(true),
-            show_nsfw: Some(false),
-            page_cursor: None,
-          },
-          get_auth_cookie.get_untracked(),
-        ));
-      });
+      let sort = ssr_sort();
+      query_caches.listing.invalidate(|(page, f, _), _| *page == 0 && f.type_ == Some(l) && f.sort == Some(sort));
       let mut query_params = query.get();
       query_params.remove("page");
       query_params.remove("list");
@@ -244,7 +205,7 @@ pub fn TopNav(
       let params = query_params.clone();
       #[cfg(not(feature = "ssr"))]
       spawn_local_scoped_with_cancellation(async move {
-        if let Ok(d) = IndexedDb::new().await {
+        if let Ok(d) = IndexedDb::shared().await {
           let _ = d.set(&ScrollPositionKey { path: use_location().pathname.get(), query: params.to_query_string() }, &0i32).await;
         }
         use_navigate()(&format!("{}{}", use_location().pathname.get(), query_params.to_query_string()), Default::default());
@@ -265,7 +226,6 @@ pub fn TopNav(
   // let logout_action = ServerAction::<LogoutFn>::new();
 
   let search_show = RwSignal::new(false);
-  let still_pressed = RwSignal::new(false);
 
   #[cfg(not(feature = "ssr"))]
   let visibility = expect_context::<Signal<VisibilityState>>();
@@ -321,52 +281,31 @@ pub fn TopNav(
     use_navigate()(&format!("/s?term={}", search_term.get()), NavigateOptions::default());
   };
 
-  let ReadInstanceCookie(get_instance_cookie) = expect_context::<ReadInstanceCookie>();
   let WriteInstanceCookie(set_instance_cookie) = expect_context::<WriteInstanceCookie>();
 
-  let instance_term = RwSignal::new(get_instance_cookie.get());
-  let on_instance_submit = move || {
-    still_pressed.set(false);
-    if instance_term.get().unwrap_or("".into()).len() > 0 {
-      set_instance_cookie.set(instance_term.get());
-    } else {
-      set_instance_cookie.set(Some("lemmy.world".to_owned()));
+  let on_instance_submit = Callback::new(move |instance: String| {
+    // the server would send a disallowed instance's visitors back to its own anyway
+    if !crate::server_config::server_config().permits(&instance) {
+      return;
     }
+    set_instance_cookie.set(Some(instance));
     if let Some(on_scroll_element) = scroll_element.get() {
       if let Some(se) = on_scroll_element.get() {
         se.set_scroll_left(0i32);
       }
     }
-    response_cache.update(move |rc| {
-      rc.remove(&(
-        0usize,
-        GetPosts {
-          type_: Some(ListingType::All),
-          sort: Some(SortType::Active),
-          page: None,
-          limit: Some(50),
-          community_id: None,
-          community_name: None,
-          saved_only: None,
-          liked_only: None,
-          disliked_only: None,
-          show_hidden: Some(true),
-          show_read: Some(true),
-          show_nsfw: Some(false),
-          page_cursor: None,
-        },
-        get_auth_cookie.get_untracked(),
-      ));
-    });
+    // nothing cached, in memory or stored, belongs to the new instance
+    query_caches.clear();
     #[cfg(not(feature = "ssr"))]
     spawn_local_scoped_with_cancellation(async move {
-      if let Ok(d) = IndexedDb::new().await {
+      if let Ok(d) = IndexedDb::shared().await {
+        let _ = d.clear_cache().await;
         let _ = d.set(&ScrollPositionKey { path: "/".into(), query: "".into() }, &0i32).await;
         use_navigate()("/", Default::default());
       }
     });
     ssr_site.refetch();
-  };
+  });
 
   let _online = expect_context::<RwSignal<OnlineSetter>>();
   // let change_theme = ServerAction::<ChangeTheme>::new();
@@ -394,71 +333,6 @@ pub fn TopNav(
     use_navigate()("/l", NavigateOptions::default());
   };
 
-  let instance_timer_handle = RwSignal::new(None);
-
-  let on_mouse_down = {
-    let timer_id = instance_timer_handle.clone();
-    move |_| {
-      let timer = set_timeout_with_handle(
-        move || {
-          still_pressed.set(true);
-          // instance_input.get().map(|i| i.focus());
-          let _ = set_timeout_with_handle(
-            move || {
-              instance_input.get().map(|i| i.focus());
-            },
-            std::time::Duration::from_millis(0),
-          )
-          .ok();
-        },
-        std::time::Duration::from_millis(500),
-      )
-      .ok();
-      timer_id.set(timer);
-    }
-  };
-
-  let on_mouse_up = {
-    let timer_id = instance_timer_handle.clone();
-    move |_| {
-      if let Some(timer) = timer_id.get() {
-        timer.clear();
-      }
-      timer_id.set(None);
-    }
-  };
-
-  let on_touch_start = {
-    let timer_id = instance_timer_handle.clone();
-    move |_| {
-      let timer = set_timeout_with_handle(
-        move || {
-          still_pressed.set(true);
-          let _ = set_timeout_with_handle(
-            move || {
-              instance_input.get().map(|i| i.focus());
-            },
-            std::time::Duration::from_millis(0),
-          )
-          .ok();
-        },
-        std::time::Duration::from_millis(500),
-      )
-      .ok();
-      timer_id.set(timer);
-    }
-  };
-
-  let on_touch_end = {
-    let timer_id = instance_timer_handle.clone();
-    move |_| {
-      if let Some(timer) = timer_id.get() {
-        timer.clear();
-      }
-      timer_id.set(None);
-    }
-  };
-
   view! {
     <Transition fallback={|| {}}>
       {move || {
@@ -479,93 +353,37 @@ pub fn TopNav(
               view! {
                 <nav class="flex flex-row py-0 navbar">
                   <div class={move || { (if search_show.get() { "hidden" } else { "flex" }).to_string() }}>
-                    <div class={move || { if still_pressed.get() { "" } else { "hidden" } }}>
-                      <input
-                        class="pl-2 sm:pl-6 h-full w-46 text-xl input"
-                        type="text"
-                        name="instance"
-                        node_ref=instance_input
-                        prop:value={move || instance_term.get()}
-                        on:keydown={move |e: KeyboardEvent| {
-                          if e.key() == "Escape" {
-                            e.prevent_default();
-                            still_pressed.set(false);
-                          }
-                        }}
-                        on:keypress={move |e: KeyboardEvent| {
-                          if e.key() == "Enter" {
-                            e.prevent_default();
-                            on_instance_submit()
-                          }
-                        }}
-                        on:input={move |ev| {
-                          instance_term.set(Some(event_target_value(&ev)));
-                        }}
-                        on:blur={move |_| {
-                          still_pressed.set(false);
-                        }}
-                      />
-                    </div>
                     <ul class="flex-nowrap items-center menu menu-horizontal">
                       <li>
                         <A
                           href="/"
-                          attr:class={move || { if still_pressed.get() { "hidden" } else { "select-none text-xl whitespace-nowrap py-1/2" } }}
-                          on:mousedown={on_mouse_down}
-                          on:mouseup={on_mouse_up}
-                          // on:mouseleave={on_mouse_up}
-                          on:touchstart={on_touch_start}
-                          on:touchend={on_touch_end}
-                          // on:mouseleave={on_mouse_up}
+                          attr:class="select-none text-xl whitespace-nowrap py-1/2"
                           on:click={move |e: MouseEvent| {
                             e.prevent_default();
-                            if still_pressed.get() {
-                              still_pressed.set(false);
-                            } else {
-                              next_page_cursor.set((0, None));
-                              #[cfg(not(feature = "ssr"))]
-                              spawn_local_scoped_with_cancellation(async move {
-                                if let Ok(d) = IndexedDb::new().await {
-                                  let _ = d
-                                    .set(
-                                      &ScrollPositionKey {
-                                        path: "/".into(),
-                                        query: "".into(),
-                                      },
-                                      &0i32,
-                                    )
-                                    .await;
-                                }
-                                response_cache
-                                  .update(move |rc| {
-                                    rc.remove(
-                                      &(
-                                        0usize,
-                                        GetPosts {
-                                          type_: Some(ListingType::All),
-                                          sort: Some(SortType::Active),
-                                          page: None,
-                                          limit: Some(50),
-                                          community_id: None,
-                                          community_name: None,
-                                          saved_only: None,
-                                          liked_only: None,
-                                          disliked_only: None,
-                                          show_hidden: Some(true),
-                                          show_read: Some(true),
-                                          show_nsfw: Some(false),
-                                          page_cursor: None,
-                                        },
-                                        get_auth_cookie.get_untracked(),
-                                      ),
-                                    );
-                                  });
-                                use_navigate()("/", Default::default());
-                              });
-                              if let Some(on_scroll_element) = scroll_element.get() {
-                                if let Some(se) = on_scroll_element.get() {
-                                  se.set_scroll_left(0i32);
-                                }
+                            next_page_cursor.set((0, None));
+                            #[cfg(not(feature = "ssr"))]
+                            spawn_local_scoped_with_cancellation(async move {
+                              if let Ok(d) = IndexedDb::shared().await {
+                                let _ = d
+                                  .set(
+                                    &ScrollPositionKey {
+                                      path: "/".into(),
+                                      query: "".into(),
+                                    },
+                                    &0i32,
+                                  )
+                                  .await;
+                              }
+                              query_caches
+                                .listing
+                                .invalidate(|(page, f, _), _| {
+                                  *page == 0 && f.type_ == Some(ListingType::All) && f.sort == Some(SortType::Active) && f.community_name.is_none()
+                                });
+                              use_navigate()("/", Default::default());
+                            });
+                            if let Some(on_scroll_element) = scroll_element.get() {
+                              if let Some(se) = on_scroll_element.get() {
+                                se.set_scroll_left(0i32);
                               }
                             }
                           }}
@@ -574,10 +392,16 @@ pub fn TopNav(
                             if let Some(Some(i)) = icon_details.get() {
                               view! { <img class="h-8 sm:hidden" src={i.inner().to_string()} /> }.into_any()
                             } else {
-                              view! { <img class="h-8" src="/favicon.png" /> }.into_any()
+                              let logo = tenant.get().and_then(|t| t.logo).unwrap_or("/favicon.png".to_owned());
+                              view! { <img class="h-8" src={logo} /> }.into_any()
                             }
                           }}
-                          <span class="hidden sm:flex">{move || { let m = site_details.get(); if m.len() > 0 { m } else { "A.O.S".to_owned() } }}</span>
+                          <span class="hidden sm:flex">
+                            {move || {
+                              let m = site_details.get();
+                              if m.len() > 0 { m } else { tenant.get().and_then(|t| t.brand).unwrap_or("A.O.S".to_owned()) }
+                            }}
+                          </span>
                         </A>
                       </li>
                       <li class="hidden sm:flex z-[1]">
@@ -876,6 +700,10 @@ pub fn TopNav(
                           </ul>
                         </details>
                       </li>
+                      <Show when={move || !tenant.get().is_some_and(|t| t.locked)} fallback={|| {}}>
+                        <InstancePicker on_switch={on_instance_submit} />
+                      </Show>
+                      <PendingMenu />
                       <Show
                         when={move || { logged_in.get() }}
                         fallback={move || {
@@ -961,6 +789,16 @@ pub fn TopNav(
                               // </A>
                               // </li>
                               // <div class="my-0 divider" />
+                              <li>
+                                <A href="/d">
+                                  <Icon icon={Pencil} />
+                                </A>
+                              </li>
+                              <li>
+                                <A href="/o">
+                                  <Icon icon={Archive} />
+                                </A>
+                              </li>
                               <li>
                                 // <ActionForm action={logout_action}>
                                 <button type="submit" on:click={on_logout_submit}>
@@ -1,4 +1,4 @@
-use crate::{ReadAuthCookie, db::csr_indexed_db::*, errors::Offline};
+use crate::{ReadAuthCookie, db::csr_indexed_db::*, emoji::*, errors::Offline, markdown::*};
 use crate::{
   // i18n::*,
   client::*,
@@ -6,6 +6,7 @@ use crate::{
   icon::{IconType::*, *},
   listings::Listings,
   nav::TopNav,
+  query_cache::QueryCaches,
 };
 use hooks::*;
 use lemmy_api_common::{
@@ -19,7 +20,7 @@ use leptos::{html::Div, leptos_dom::helpers::TimeoutHandle, logging::error, prel
 use leptos_router::{components::*, location::State, *};
 use leptos_use::*;
 use send_wrapper::SendWrapper;
-use std::{collections::BTreeMap, usize, vec};
+use std::{usize, vec};
 use web_sys::{Event, MouseEvent, WheelEvent};
 
 #[component]
@@ -30,7 +31,7 @@ pub fn Overview(#[prop(optional)] ssr_name: Signal<Option<String>>) -> impl Into
   let ssr_sort = move || serde_json::from_str::<SortType>(&use_query_map().get().get("sort").unwrap_or("".into())).unwrap_or(SortType::Active);
   let ssr_page = move || serde_json::from_str::<Vec<(usize, String)>>(&use_query_map().get().get("page").unwrap_or("".into())).unwrap_or(vec![]);
 
-  let response_cache = expect_context::<RwSignal<BTreeMap<(usize, GetPosts, Option<String>), (i64, LemmyAppResult<GetPostsResponse>)>>>();
+  let query_caches = expect_context::<QueryCaches>();
   let next_page_cursor: RwSignal<(usize, Option<PaginationCursor>)> = RwSignal::new((0, None));
 
   let loading = RwSignal::new(false);
@@ -39,16 +40,14 @@ pub fn Overview(#[prop(optional)] ssr_name: Signal<Option<String>>) -> impl Into
   let intersection_element = NodeRef::<Div>::new();
   let on_scroll_element = NodeRef::<Div>::new();
 
+  #[cfg(not(feature = "ssr"))]
+  let shared_db = expect_context::<SharedIndexedDb>();
+  #[cfg(not(feature = "ssr"))]
+  let location = use_location();
   let on_scroll = move |_e: Event| {
     #[cfg(not(feature = "ssr"))]
     if let Some(se) = on_scroll_element.get() {
-      spawn_local_scoped_with_cancellation(async move {
-        if let Ok(d) = IndexedDb::new().await {
-          let _ = d
-            .set(&ScrollPositionKey { path: use_location().pathname.get(), query: use_query_map().get().to_query_string() }, &se.scroll_left())
-            .await;
-        }
-      });
+      shared_db.save_scroll_position(ScrollPositionKey { path: location.pathname.get_untracked(), query: use_query_map().get_untracked().to_query_string() }, se.scroll_left());
     }
   };
 
@@ -79,7 +78,7 @@ pub fn Overview(#[prop(optional)] ssr_name: Signal<Option<String>>) -> impl Into
             if let Some(se) = on_scroll_element.get() {
               let params = query_params.clone();
               spawn_local_scoped_with_cancellation(async move {
-                if let Ok(d) = IndexedDb::new().await {
+                if let Ok(d) = IndexedDb::shared().await {
                   let _ = d.set(&ScrollPositionKey { path: use_location().pathname.get(), query: params.to_query_string() }, &se.scroll_left()).await;
                 }
                 use_navigate()(
@@ -98,7 +97,7 @@ pub fn Overview(#[prop(optional)] ssr_name: Signal<Option<String>>) -> impl Into
   #[cfg(not(feature = "ssr"))]
   fn load_cache(fc: GetPosts) -> impl std::future::Future<Output = Result<GetPostsResponse, LemmyAppError>> {
     SendWrapper::new(async move {
-      if let Ok(d) = IndexedDb::new().await {
+      if let Ok(d) = IndexedDb::shared().await {
         if let Ok(c) = d.load::<GetPosts, Result<GetPostsResponse, LemmyAppError>>(&fc).await {
           if let Some(r) = c { r } else { Err(LemmyAppError { error_type: LemmyAppErrorType::Unknown, content: "".to_owned() }) }
         } else {
@@ -110,6 +109,9 @@ pub fn Overview(#[prop(optional)] ssr_name: Signal<Option<String>>) -> impl Into
     })
   }
 
+  // a fresh page arriving after a cached copy was shown
+  let fresh_posts = RwSignal::new(None::<(GetPosts, GetPostsResponse)>);
+
   let post_list_resource = Resource::new(
     move || (ssr_list(), ssr_sort(), ssr_name.get(), ssr_page()),
     move |(list, sort, name, mut pages)| async move {
@@ -129,7 +131,6 @@ pub fn Overview(#[prop(optional)] ssr_name: Signal<Option<String>>) -> impl Into
       loading.set(true);
 
       let ReadAuthCookie(get_auth_cookie) = expect_context::<ReadAuthCookie>();
-      let rc = response_cache.get_untracked();
       let mut new_pages: Vec<(usize, GetPosts, i64, LemmyAppResult<GetPostsResponse>, Option<String>, bool, bool)> = vec![];
       if pages.len() == 0 {
         pages = vec![(0usize, "".to_owned())];
@@ -152,8 +153,8 @@ pub fn Overview(#[prop(optional)] ssr_name: Signal<Option<String>>) -> impl Into
         };
 
         #[cfg(not(feature = "ssr"))]
-        if let Some((t, Ok(r))) = rc.get(&(p.0, form.clone(), get_auth_cookie.get_untracked())) {
-          new_pages.push((p.0, form.clone(), t.clone(), Ok(r.clone()), get_auth_cookie.get_untracked(), do_not_render_scroll, csr_cache_render));
+        if let Some((t, r)) = query_caches.listing.get(&(p.0, form.clone(), get_auth_cookie.get_untracked())) {
+          new_pages.push((p.0, form.clone(), t, Ok(r), get_auth_cookie.get_untracked(), do_not_render_scroll, csr_cache_render));
           continue;
         } else {
           if many_pages {
@@ -176,7 +177,8 @@ pub fn Overview(#[prop(optional)] ssr_name: Signal<Option<String>>) -> impl Into
           }
         }
 
-        let result = match LemmyClient.list_posts(form.clone()).await {
+        let fresh_form = form.clone();
+        let result = match LemmyClient.list_posts_revalidating(form.clone(), Callback::new(move |o| fresh_posts.set(Some((fresh_form.clone(), o))))).await {
           Ok(mut o) => {
             o.posts.retain(|p| !p.banned_from_community);
             Ok(o)
@@ -198,6 +200,18 @@ pub fn Overview(#[prop(optional)] ssr_name: Signal<Option<String>>) -> impl Into
     },
   );
 
+  Effect::new(move |_| {
+    if let Some((form, mut o)) = fresh_posts.get() {
+      o.posts.retain(|p| !p.banned_from_community);
+      post_list_resource.update(|pages| {
+        for page in pages.iter_mut().flatten().filter(|page| page.1 == form) {
+          page.2 = jiff::Zoned::now().timestamp().as_millisecond();
+          page.3 = Ok(o.clone());
+        }
+      });
+    }
+  });
+
   let details_resource = Resource::new(
     move || ssr_name.get(),
     move |name| async move {
@@ -277,27 +291,7 @@ pub fn Overview(#[prop(optional)] ssr_name: Signal<Option<String>>) -> impl Into
                 Some(Ok(Some(s))) => {
                   let community_title_encoded = html_escape::encode_safe(&s.community_view.community.title).to_string();
                   let description = if let Some(description) = s.community_view.community.description {
-                    let mut options = pulldown_cmark::Options::empty();
-                    options.insert(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
-                    options.insert(pulldown_cmark::Options::ENABLE_TABLES);
-                    options.insert(pulldown_cmark::Options::ENABLE_SUPERSCRIPT);
-                    options.insert(pulldown_cmark::Options::ENABLE_SUBSCRIPT);
-                    options.insert(pulldown_cmark::Options::ENABLE_CONTAINER_EXTENSIONS);
-                    let parser = pulldown_cmark::Parser::new_ext(&description, options);
-                    let custom = parser
-                      .map(|event| match event {
-                        pulldown_cmark::Event::Html(text) => {
-                          let er = format!("<p>{}</p>", html_escape::encode_safe(&text).to_string());
-                          pulldown_cmark::Event::Html(er.into())
-                        }
-                        pulldown_cmark::Event::InlineHtml(text) => {
-                          let er = html_escape::encode_safe(&text).to_string();
-                          pulldown_cmark::Event::InlineHtml(er.into())
-                        }
-                        _ => event,
-                      });
-                    let mut description_encoded = String::new();
-                    pulldown_cmark::html::push_html(&mut description_encoded, custom);
+                    let description_encoded = markdown_to_html(&description, Profile::Sidebar, custom_emojis(ssr_site));
                     description_encoded
                   } else {
                     String::new()
@@ -360,7 +354,15 @@ pub fn Overview(#[prop(optional)] ssr_name: Signal<Option<String>>) -> impl Into
                         </div>
                       </div>
                       <div class="py-2 px-4" style={move || { if show_rules.get() { "display: block;" } else { "display: none;" } }}>
-                        <div class="select-none prose" inner_html={description} />
+                        <div
+                          class="select-none prose"
+                          on:click={move |e: MouseEvent| {
+                            if let Some(t) = e.target() {
+                              copy_code(&t);
+                            }
+                          }}
+                          inner_html={description}
+                        />
                       </div>
                     </div>
                   }
@@ -381,13 +383,12 @@ pub fn Overview(#[prop(optional)] ssr_name: Signal<Option<String>>) -> impl Into
                     use crate::db::csr_indexed_db::*;
                     spawn_local_scoped_with_cancellation(async move {
                       if p.6 {} else {
-                        if let Ok(d) = IndexedDb::new().await {
+                        if let Ok(d) = IndexedDb::shared().await {
                           if let Ok(_c) = d.set::<GetPosts, Result<GetPostsResponse, LemmyAppError>>(&fm, &rw).await {}
                         }
-                        response_cache
-                          .update(move |rc| {
-                            rc.insert((p.0, fm, p.4), (p.2, rw));
-                          });
+                        if let Ok(r) = rw {
+                          query_caches.listing.insert((p.0, fm, p.4), p.2, r);
+                        }
                       }
                     });
                     let iw = window().inner_width().ok().map(|b| b.as_f64().unwrap_or(0.0)).unwrap_or(0.0);
@@ -401,7 +402,7 @@ pub fn Overview(#[prop(optional)] ssr_name: Signal<Option<String>>) -> impl Into
                               move || {
                                 if let Some(s) = on_scroll_element.get() {
                                   spawn_local_scoped_with_cancellation(async move {
-                                    if let Ok(d) = IndexedDb::new().await {
+                                    if let Ok(d) = IndexedDb::shared().await {
                                       let l: Result<Option<i32>, Error> = d
                                         .get(
                                           &ScrollPositionKey {
@@ -0,0 +1,230 @@
+use crate::{
+  client::*,
+  db::csr_indexed_db::*,
+  errors::{LemmyAppError, LemmyAppErrorType, LemmyAppResult},
+  icon::{Icon, IconType::*},
+};
+use lemmy_api_common::LemmyErrorType;
+use leptos::{prelude::*, task::spawn_local_scoped};
+use web_sys::MouseEvent;
+
+#[derive(Clone, Debug)]
+pub struct ReplayReport {
+  pub action: PendingAction,
+  pub error: LemmyAppError,
+  // the server turned the action down, as opposed to it failing to arrive
+  pub conflict: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct PendingActions {
+  pub queued: RwSignal<Vec<(PendingActionKey, PendingAction)>>,
+  pub reports: RwSignal<Vec<ReplayReport>>,
+  replaying: RwSignal<bool>,
+}
+
+impl PendingActions {
+  pub fn new() -> Self {
+    Self { queued: RwSignal::new(vec![]), reports: RwSignal::new(vec![]), replaying: RwSignal::new(false) }
+  }
+}
+
+impl PendingAction {
+  pub fn describe(&self) -> String {
+    let vote = |score: i16| match score {
+      1 => "Up vote",
+      -1 => "Down vote",
+      _ => "Remove vote on",
+    };
+    let preview = |content: &str| content.chars().take(60).collect::<String>();
+    match self {
+      PendingAction::LikePost(f) => format!("{} post {}", vote(f.score), f.post_id),
+      PendingAction::LikeComment(f) => format!("{} comment {}", vote(f.score), f.comment_id),
+      PendingAction::SavePost(f) => format!("{} post {}", if f.save { "Save" } else { "Unsave" }, f.post_id),
+      PendingAction::SaveComment(f) => format!("{} comment {}", if f.save { "Save" } else { "Unsave" }, f.comment_id),
+      PendingAction::ReplyComment(f) => match f.parent_id {
+        Some(p) => format!("Reply to comment {}: {}", p, preview(&f.content)),
+        None => format!("Comment on post {}: {}", f.post_id, preview(&f.content)),
+      },
+      PendingAction::EditComment(f) => format!("Edit comment {}: {}", f.comment_id, preview(f.content.as_deref().unwrap_or(""))),
+    }
+  }
+
+  // the draft a reply or edit was typed into, cleared once the action lands
+  fn draft_key(&self) -> Option<CommentDraftKey> {
+    match self {
+      PendingAction::ReplyComment(f) => Some(match f.parent_id {
+        Some(p) => CommentDraftKey { comment_id: p.0, draft: Draft::Reply },
+        None => CommentDraftKey { comment_id: f.post_id.0, draft: Draft::Post },
+      }),
+      PendingAction::EditComment(f) => Some(CommentDraftKey { comment_id: f.comment_id.0, draft: Draft::Edit }),
+      _ => None,
+    }
+  }
+
+  async fn send(self) -> LemmyAppResult<()> {
+    match self {
+      PendingAction::LikePost(f) => LemmyClient.like_post(f).await.map(|_| ()),
+      PendingAction::LikeComment(f) => LemmyClient.like_comment(f).await.map(|_| ()),
+      PendingAction::SavePost(f) => LemmyClient.save_post(f).await.map(|_| ()),
+      PendingAction::SaveComment(f) => LemmyClient.save_comment(f).await.map(|_| ()),
+      PendingAction::ReplyComment(f) => LemmyClient.reply_comment(f).await.map(|_| ()),
+      PendingAction::EditComment(f) => LemmyClient.edit_comment(f).await.map(|_| ()),
+    }
+  }
+}
+
+pub fn is_queued<T>(result: &LemmyAppResult<T>) -> bool {
+  matches!(result, Err(LemmyAppError { error_type: LemmyAppErrorType::Queued, .. }))
+}
+
+// stores the action when the request failed for being offline, callers then see a Queued error instead
+pub async fn queue_when_offline<T>(result: LemmyAppResult<T>, action: PendingAction) -> LemmyAppResult<T> {
+  let Err(LemmyAppError { error_type: LemmyAppErrorType::OfflineError, .. }) = result else {
+    return result;
+  };
+  let Some(pending) = use_context::<PendingActions>() else {
+    return result;
+  };
+  if pending.replaying.get_untracked() {
+    return result;
+  }
+  #[cfg(not(feature = "ssr"))]
+  if let Ok(_) = pending.enqueue(action.clone()).await {
+    return Err(LemmyAppError { error_type: LemmyAppErrorType::Queued, content: action.describe() });
+  }
+  result
+}
+
+#[cfg(not(feature = "ssr"))]
+impl PendingActions {
+  async fn enqueue(self, action: PendingAction) -> Result<(), Error> {
+    let d = IndexedDb::shared().await?;
+    let now_in_millis = jiff::Timestamp::now().as_millisecond();
+    // keys double as replay order so two actions in the same millisecond must not collide
+    let queued = self.queued.with_untracked(|q| q.last().map(|(k, _)| (k.queued + 1).max(now_in_millis))).unwrap_or(now_in_millis);
+    let key = PendingActionKey { queued };
+    d.set(&key, &action).await?;
+    self.queued.update(|q| q.push((key, action)));
+    Ok(())
+  }
+
+  pub async fn load(self) {
+    if let Ok(d) = IndexedDb::shared().await
+      && let Ok(mut entries) = d.entries::<PendingActionKey, PendingAction>("pending_actions").await
+    {
+      entries.sort_by_key(|(k, _)| k.queued);
+      self.queued.set(entries);
+    }
+  }
+
+  // sends queued actions oldest first, stopping at the first one that fails to arrive so later ones keep their order
+  pub async fn replay(self) {
+    if self.replaying.get_untracked() {
+      return;
+    }
+    self.replaying.set(true);
+    self.load().await;
+    if let Ok(d) = IndexedDb::shared().await {
+      for (key, action) in self.queued.get_untracked() {
+        let result = action.clone().send().await;
+        let conflict = match &result {
+          Ok(_) => false,
+          Err(LemmyAppError { error_type: LemmyAppErrorType::OfflineError, .. }) => break,
+          Err(LemmyAppError { error_type: LemmyAppErrorType::ApiError(LemmyErrorType::IncorrectLogin | LemmyErrorType::NotLoggedIn), .. }) => false,
+          Err(LemmyAppError { error_type: LemmyAppErrorType::ApiError(_), .. }) => true,
+          Err(_) => false,
+        };
+        if result.is_ok() || conflict {
+          let _ = d.del(&key).await;
+          self.queued.update(|q| q.retain(|(k, _)| *k != key));
+        }
+        match result {
+          Ok(_) => {
+            if let Some(draft_key) = action.draft_key() {
+              let _ = d.del(&draft_key).await;
+            }
+          }
+          Err(error) => {
+            self.reports.update(|r| r.push(ReplayReport { action, error, conflict }));
+            if !conflict {
+              break;
+            }
+          }
+        }
+      }
+    }
+    self.replaying.set(false);
+  }
+}
+
+#[component]
+pub fn PendingMenu() -> impl IntoView {
+  let pending = expect_context::<PendingActions>();
+
+  let on_retry = move |e: MouseEvent| {
+    e.prevent_default();
+    pending.reports.set(vec![]);
+    #[cfg(not(feature = "ssr"))]
+    spawn_local_scoped(pending.replay());
+  };
+
+  let on_dismiss = move |e: MouseEvent| {
+    e.prevent_default();
+    pending.reports.set(vec![]);
+  };
+
+  view! {
+    <Show when={move || !pending.queued.get().is_empty() || !pending.reports.get().is_empty()} fallback={|| {}}>
+      <li>
+        <details>
+          <summary title="Pending actions">
+            <Icon icon={History} />
+            <span class={move || {
+              format!("badge badge-sm{}", if pending.reports.get().iter().any(|r| !r.conflict) { " badge-error" } else { " badge-neutral" })
+            }}>{move || pending.queued.get().len()}</span>
+          </summary>
+          <ul class="z-[1] w-72 [inset-inline-end:0]">
+            <For each={move || pending.queued.get()} key={|(k, _)| k.queued} let:entry>
+              <li class="text-sm">
+                <span>
+                  <span class="badge badge-sm badge-neutral">"pending"</span>
+                  {entry.1.describe()}
+                </span>
+              </li>
+            </For>
+            {move || {
+              pending
+                .reports
+                .get()
+                .into_iter()
+                .map(|r| {
+                  view! {
+                    <li class="text-sm">
+                      <span>
+                        <span class={format!("badge badge-sm{}", if r.conflict { " badge-warning" } else { " badge-error" })}>
+                          {if r.conflict { "conflict" } else { "failed" }}
+                        </span>
+                        {format!("{} ({})", r.action.describe(), r.error.content)}
+                      </span>
+                    </li>
+                  }
+                })
+                .collect_view()
+            }}
+            <li>
+              <div class="flex gap-2">
+                <button type="button" class="btn btn-ghost btn-xs" on:click={on_retry}>
+                  "Retry"
+                </button>
+                <button type="button" class="btn btn-ghost btn-xs" disabled={move || pending.reports.get().is_empty()} on:click={on_dismiss}>
+                  "Dismiss"
+                </button>
+              </div>
+            </li>
+          </ul>
+        </details>
+      </li>
+    </Show>
+  }
+}
@@ -1,16 +1,24 @@
 use crate::{
-  OnlineSetter, ReadInstanceCookie,
+  ReadInstanceCookie,
   client::*,
   comments::Comments,
   db::csr_indexed_db::*,
+  editor::MarkdownEditor,
+  emoji::*,
   errors::{LemmyAppError, LemmyAppErrorType, Loading},
+  markdown::*,
+  mention::participants,
   nav::TopNav,
+  pending::is_queued,
   toolbar::PostToolbar,
 };
 use ev::MouseEvent;
 use lemmy_api_common::{
-  comment::{CreateComment, GetComments},
-  lemmy_db_schema::{CommentSortType, SortType, newtypes::PostId},
+  comment::{CreateComment, GetComment, GetComments, GetCommentsResponse},
+  lemmy_db_schema::{
+    CommentSortType, SortType,
+    newtypes::{CommentId, PostId},
+  },
   post::{GetPost, GetPostResponse},
   site::GetSiteResponse,
 };
@@ -25,6 +33,15 @@ use leptos_router::{components::A, hooks::*};
 use leptos_use::{UseIntersectionObserverOptions, use_intersection_observer_with_options, *};
 use web_sys::{Event, HtmlAnchorElement, HtmlImageElement, WheelEvent, wasm_bindgen::JsCast};
 
+const COMMENT_CONTEXT_LEVELS: usize = 3;
+
+// picks the ancestor `context` levels above the last comment in a "0.a.b.c" path
+fn context_root_id(path: &str, context: usize) -> Option<i32> {
+  let ids = path.split('.').skip(1).filter_map(|s| s.parse::<i32>().ok()).collect::<Vec<_>>();
+  let last = ids.len().checked_sub(1)?;
+  ids.get(last - context.min(last)).copied()
+}
+
 #[component]
 pub fn Post() -> impl IntoView {
   let ssr_site = expect_context::<Resource<Result<GetSiteResponse, LemmyAppError>>>();
@@ -32,8 +49,9 @@ pub fn Post() -> impl IntoView {
   let query = use_query_map();
 
   let post_id = Signal::derive(move || params.get().get("id").unwrap_or_default().parse::<i32>().ok());
-  let online = expect_context::<RwSignal<OnlineSetter>>();
+  let comment_id = Signal::derive(move || params.get().get("comment_id").unwrap_or_default().parse::<i32>().ok());
   let ssr_sort = move || serde_json::from_str::<CommentSortType>(&query.get().get("sort").unwrap_or("".into())).unwrap_or(CommentSortType::Top);
+  let ssr_context = move || query.get().get("context").unwrap_or_default().parse::<usize>().unwrap_or(COMMENT_CONTEXT_LEVELS);
 
   let reply_show = RwSignal::new(false);
   let content = RwSignal::new(String::default());
@@ -41,14 +59,36 @@ pub fn Post() -> impl IntoView {
 
   let post_view = RwSignal::new(None::<GetPostResponse>);
 
+  // resuming a draft from the drafts page opens the reply editor straight away
+  #[cfg(not(feature = "ssr"))]
+  Effect::new(move |_| {
+    if query.get_untracked().get("draft").as_deref() == Some("post")
+      && let Some(id) = post_id.get_untracked()
+    {
+      spawn_local_scoped_with_cancellation(async move {
+        if let Ok(d) = IndexedDb::shared().await
+          && let Ok(Some(c)) = d.get::<CommentDraftKey, CommentDraft>(&CommentDraftKey { comment_id: id, draft: Draft::Post }).await
+        {
+          content.set(c.value);
+        }
+        reply_show.set(true);
+      });
+    }
+  });
+
+  // fresh responses arriving after a cached copy was shown
+  let fresh_post = RwSignal::new(None::<(GetPost, GetPostResponse)>);
+  let fresh_comments = RwSignal::new(None::<(GetComments, GetCommentsResponse)>);
+
   let post_resource = Resource::new(
-    move || post_id.get(),
-    move |id_string| async move {
+    move || (post_id.get(), comment_id.get()),
+    move |(id_string, comment_id)| async move {
       if let Some(id) = id_string {
-        let form = GetPost { id: Some(PostId(id)), comment_id: None };
+        let form = GetPost { id: Some(PostId(id)), comment_id: comment_id.map(CommentId) };
         #[cfg(not(feature = "ssr"))]
         loading.set(true);
-        let result = LemmyClient.get_post(form.clone()).await;
+        let fresh_form = form.clone();
+        let result = LemmyClient.get_post_revalidating(form.clone(), Callback::new(move |o| fresh_post.set(Some((fresh_form.clone(), o))))).await;
         match result {
           Ok(o) => Some(Ok((form, o))),
           Err(e) => Some(Err(e)),
@@ -59,10 +99,27 @@ pub fn Post() -> impl IntoView {
     },
   );
 
+  Effect::new(move |_| {
+    if let Some((form, o)) = fresh_post.get()
+      && form.id.map(|i| i.0) == post_id.get_untracked()
+      && form.comment_id.map(|c| c.0) == comment_id.get_untracked()
+    {
+      post_resource.set(Some(Some(Ok((form, o)))));
+    }
+  });
+
   let comments_resource = Resource::new(
-    move || (post_id.get(), ssr_sort()),
-    move |(post_id, sort_type)| async move {
+    move || (post_id.get(), ssr_sort(), comment_id.get(), ssr_context()),
+    move |(post_id, sort_type, comment_id, context)| async move {
       if let Some(id) = post_id {
+        let parent_id = if let Some(c) = comment_id {
+          match LemmyClient.get_comment(GetComment { id: CommentId(c) }).await {
+            Ok(o) => Some(CommentId(context_root_id(&o.comment_view.comment.path, context).unwrap_or(c))),
+            Err(_e) => Some(CommentId(c)),
+          }
+        } else {
+          None
+        };
         let form = GetComments {
           post_id: Some(PostId(id)),
           community_id: None,
@@ -72,12 +129,14 @@ pub fn Post() -> impl IntoView {
           page: None,
           limit: None,
           community_name: None,
-          parent_id: None,
+          parent_id,
           saved_only: None,
           disliked_only: None,
           liked_only: None,
         };
-        let result = LemmyClient.get_comments(form.clone()).await;
+        let fresh_form = form.clone();
+        let result =
+          LemmyClient.get_comments_revalidating(form.clone(), Callback::new(move |o| fresh_comments.set(Some((fresh_form.clone(), o))))).await;
         match result {
           Ok(o) => Some((form, o)),
           Err(_e) => None,
@@ -88,6 +147,15 @@ pub fn Post() -> impl IntoView {
     },
   );
 
+  Effect::new(move |_| {
+    if let Some((form, o)) = fresh_comments.get()
+      && form.post_id.map(|p| p.0) == post_id.get_untracked()
+      && form.sort == Some(untrack(ssr_sort))
+    {
+      comments_resource.set(Some(Some((form, o))));
+    }
+  });
+
   let _on_sort_click = move |s: CommentSortType| {
     move |_e: MouseEvent| {
       let r = serde_json::to_string::<CommentSortType>(&s);
@@ -117,10 +185,13 @@ pub fn Post() -> impl IntoView {
             comments_resource.refetch();
             reply_show.update(|b| *b = !*b);
             #[cfg(not(feature = "ssr"))]
-            if let Ok(d) = IndexedDb::new().await {
+            if let Ok(d) = IndexedDb::shared().await {
               if let Ok(_c) = d.del(&CommentDraftKey { comment_id: id, draft: Draft::Post }).await {}
             }
           }
+          Err(_) if is_queued(&result) => {
+            reply_show.set(false);
+          }
           Err(_e) => {}
         }
       }
@@ -130,16 +201,14 @@ pub fn Post() -> impl IntoView {
   let _visibility_element = NodeRef::<Textarea>::new();
 
   let on_scroll_element = NodeRef::<Div>::new();
+  #[cfg(not(feature = "ssr"))]
+  let shared_db = expect_context::<SharedIndexedDb>();
+  #[cfg(not(feature = "ssr"))]
+  let location = use_location();
   let on_scroll = move |_e: Event| {
     #[cfg(not(feature = "ssr"))]
     if let Some(se) = on_scroll_element.get() {
-      spawn_local_scoped_with_cancellation(async move {
-        if let Ok(d) = IndexedDb::new().await {
-          let _ = d
-            .set(&ScrollPositionKey { path: use_location().pathname.get(), query: use_query_map().get().to_query_string() }, &se.scroll_left())
-            .await;
-        }
-      });
+      shared_db.save_scroll_position(ScrollPositionKey { path: location.pathname.get_untracked(), query: query.get_untracked().to_query_string() }, se.scroll_left());
     }
   };
 
@@ -249,7 +318,7 @@ pub fn Post() -> impl IntoView {
                       let fm = res.0.clone();
                       use crate::db::csr_indexed_db::*;
                       spawn_local_scoped_with_cancellation(async move {
-                        if let Ok(d) = IndexedDb::new().await {
+                        if let Ok(d) = IndexedDb::shared().await {
                           if let Ok(_c) = d.set(&fm, &rw).await {}
                         }
                       });
@@ -262,29 +331,7 @@ pub fn Post() -> impl IntoView {
                       None
                     };
                     let title = post_response.get().post_view.post.name.clone();
-                    let mut options = pulldown_cmark::Options::empty();
-                    options.insert(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
-                    options.insert(pulldown_cmark::Options::ENABLE_TABLES);
-                    options.insert(pulldown_cmark::Options::ENABLE_SUPERSCRIPT);
-                    options.insert(pulldown_cmark::Options::ENABLE_SUBSCRIPT);
-                    options.insert(pulldown_cmark::Options::ENABLE_CONTAINER_EXTENSIONS);
-                    options.insert(pulldown_cmark::Options::ENABLE_LINKIFY_LEMMY);
-                    options.insert(pulldown_cmark::Options::ENABLE_LINKIFY_HTTP);
-                    let parser = pulldown_cmark::Parser::new_ext(&title, options);
-                    let custom = parser
-                      .map(|event| match event {
-                        pulldown_cmark::Event::Html(text) => {
-                          let er = format!("<p>{}</p>", html_escape::encode_safe(&text).to_string());
-                          pulldown_cmark::Event::Html(er.into())
-                        }
-                        pulldown_cmark::Event::InlineHtml(text) => {
-                          let er = html_escape::encode_safe(&text).to_string();
-                          pulldown_cmark::Event::InlineHtml(er.into())
-                        }
-                        _ => event,
-                      });
-                    let mut title_encoded = String::new();
-                    pulldown_cmark::html::push_html(&mut title_encoded, custom);
+                    let title_encoded = markdown_to_html(&title, Profile::Title, custom_emojis(ssr_site));
                     let community_title = if post_response.get().post_view.community.local {
                       format!("{}", post_response.get().post_view.community.name)
                     } else {
@@ -368,7 +415,7 @@ pub fn Post() -> impl IntoView {
                               e.prevent_default();
                               #[cfg(not(feature = "ssr"))]
                               spawn_local_scoped_with_cancellation(async move {
-                                if let Ok(d) = IndexedDb::new().await {
+                                if let Ok(d) = IndexedDb::shared().await {
                                   let _ = d
                                     .set(
                                       &ScrollPositionKey {
@@ -451,29 +498,7 @@ pub fn Post() -> impl IntoView {
                       </a>
 
                       {if let Some(ref content) = text {
-                        let mut options = pulldown_cmark::Options::empty();
-                        options.insert(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
-                        options.insert(pulldown_cmark::Options::ENABLE_TABLES);
-                        options.insert(pulldown_cmark::Options::ENABLE_SUPERSCRIPT);
-                        options.insert(pulldown_cmark::Options::ENABLE_SUBSCRIPT);
-                        options.insert(pulldown_cmark::Options::ENABLE_CONTAINER_EXTENSIONS);
-                        options.insert(pulldown_cmark::Options::ENABLE_LINKIFY_LEMMY);
-                        options.insert(pulldown_cmark::Options::ENABLE_LINKIFY_HTTP);
-                        let parser = pulldown_cmark::Parser::new_ext(content, options);
-                        let custom = parser
-                          .map(|event| match event {
-                            pulldown_cmark::Event::Html(text) => {
-                              let er = format!("<p>{}</p>", html_escape::encode_safe(&text).to_string());
-                              pulldown_cmark::Event::Html(er.into())
-                            }
-                            pulldown_cmark::Event::InlineHtml(text) => {
-                              let er = html_escape::encode_safe(&text).to_string();
-                              pulldown_cmark::Event::InlineHtml(er.into())
-                            }
-                            _ => event,
-                          });
-                        let mut safe_html = String::new();
-                        pulldown_cmark::html::push_html(&mut safe_html, custom);
+                        let safe_html = markdown_to_html(content, Profile::Body, custom_emojis(ssr_site));
                         Some(
                           view! {
                             <div class="pr-4 pl-4 before:content-[''] before:block before:w-24 before:overflow-hidden">
@@ -481,7 +506,8 @@ pub fn Post() -> impl IntoView {
                                 class="py-2"
                                 on:click={move |e: MouseEvent| {
                                   if let Some(t) = e.target() {
-                                    if let Some(i) = t.dyn_ref::<HtmlImageElement>() {
+                                    if copy_code(&t) {
+                                    } else if let Some(i) = t.dyn_ref::<HtmlImageElement>() {
                                       let _ = window().open_with_url_and_target(&i.src(), "_blank");
                                     } else if let Some(l) = t.dyn_ref::<HtmlAnchorElement>() {
                                       e.prevent_default();
@@ -509,37 +535,16 @@ pub fn Post() -> impl IntoView {
                                   <Show when={move || reply_show.get()} fallback={|| {}}>
                                     <div class="pr-4 pl-4 mb-3 space-y-3 before:content-[''] before:block before:w-24 before:overflow-hidden">
                                       <div class="form-control">
-                                        <textarea
-                                          class="h-24 w-full text-base textarea textarea-bordered"
-                                          placeholder="Comment text"
-                                          prop:value={move || content.get()}
-                                          node_ref={_visibility_element}
-                                          on:wheel={move |e: WheelEvent| {
-                                            e.stop_propagation();
-                                          }}
-                                          on:input={move |ev| {
-                                            content.set(event_target_value(&ev));
-                                            if let Some(id) = post_id.get() {
-                                              #[cfg(not(feature = "ssr"))]
-                                              spawn_local_scoped_with_cancellation(async move {
-                                                if let Ok(d) = IndexedDb::new().await {
-                                                  if let Ok(_c) = d
-                                                    .set(
-                                                      &CommentDraftKey {
-                                                        comment_id: id,
-                                                        draft: Draft::Post,
-                                                      },
-                                                      &content.get(),
-                                                    )
-                                                    .await
-                                                  {}
-                                                }
-                                              });
-                                            }
-                                          }}
-                                        >
-                                          {content.get_untracked()}
-                                        </textarea>
+                                        <MarkdownEditor
+                                          content
+                                          textarea={_visibility_element}
+                                          draft_key={Signal::derive(move || post_id.get().map(|id| CommentDraftKey { comment_id: id, draft: Draft::Post }))}
+                                          draft_post_id={post_id}
+                                          draft_context={Signal::derive(move || post_view.get().map(|p| p.post_view.post.name).unwrap_or_default())}
+                                          participants={Signal::derive(move || {
+                                            comments_resource.get().flatten().map(|res| participants(&res.1.comments)).unwrap_or_default()
+                                          })}
+                                        />
                                       </div>
                                       <div class="form-control">
                                         <button
@@ -549,11 +554,11 @@ pub fn Post() -> impl IntoView {
                                             format!(
                                               "btn btn-neutral{}",
                                               {
-                                                if !logged_in.get() || !online.get().0 { " text-base-content/50" } else { " hover:text-secondary/50" }
+                                                if !logged_in.get() { " text-base-content/50" } else { " hover:text-secondary/50" }
                                               },
                                             )
                                           }}
-                                          disabled={move || !logged_in.get() || !online.get().0}
+                                          disabled={move || !logged_in.get()}
                                         >
                                           "Comment"
                                         </button>
@@ -588,12 +593,12 @@ pub fn Post() -> impl IntoView {
                       let fm = res.0.clone();
                       use crate::db::csr_indexed_db::*;
                       spawn_local_scoped_with_cancellation(async move {
-                        if let Ok(d) = IndexedDb::new().await {
+                        if let Ok(d) = IndexedDb::shared().await {
                           if let Ok(_c) = d.set(&fm, &rw).await {}
                         }
                       });
                       let iw = window().inner_width().ok().map(|b| b.as_f64().unwrap_or(0.0)).unwrap_or(0.0);
-                      if iw < 768f64 {} else {
+                      if iw < 768f64 || comment_id.get_untracked().is_some() {} else {
                         if let Some(c) = cancel_handle.get_untracked() {
                           c.clear();
                         }
@@ -603,7 +608,7 @@ pub fn Post() -> impl IntoView {
                                 move || {
                                   if let Some(s) = on_scroll_element.get() {
                                     spawn_local_scoped_with_cancellation(async move {
-                                      if let Ok(d) = IndexedDb::new().await {
+                                      if let Ok(d) = IndexedDb::shared().await {
                                         let l: Result<Option<i32>, Error> = d
                                           .get(
                                             &ScrollPositionKey {
@@ -628,8 +633,15 @@ pub fn Post() -> impl IntoView {
                     let res = res.1.clone();
 
                     view! {
+                      <Show when={move || comment_id.get().is_some()} fallback={|| {}}>
+                        <div class="py-2 px-4 before:content-[''] before:block before:w-24 before:overflow-hidden">
+                          <A href={move || format!("/p/{}", post_id.get().unwrap_or_default())} attr:class="text-sm hover:text-accent">
+                            "View all comments"
+                          </A>
+                        </div>
+                      </Show>
                       <div class="w-full before:content-[''] before:block before:w-24 before:overflow-hidden">
-                        <Comments comments={res.comments.into()} post_id />
+                        <Comments comments={res.comments.into()} post_id highlight_comment_id={comment_id} />
                       </div>
                     }
                   })
@@ -0,0 +1,144 @@
+use crate::errors::LemmyAppResult;
+use lemmy_api_common::{
+  comment::{GetComments, GetCommentsResponse},
+  lemmy_db_schema::newtypes::{CommentId, PersonId, PostId},
+  post::{GetPost, GetPostResponse, GetPosts, GetPostsResponse},
+};
+use leptos::prelude::*;
+use std::collections::BTreeMap;
+
+// responses kept in memory between route changes, each stamped with when it was fetched
+pub struct QueryCache<K, V>
+where
+  K: Ord + Clone + Send + Sync + 'static,
+  V: Clone + Send + Sync + 'static,
+{
+  entries: RwSignal<BTreeMap<K, (i64, V)>>,
+  ttl_millis: i64,
+  max_entries: usize,
+}
+
+impl<K, V> Clone for QueryCache<K, V>
+where
+  K: Ord + Clone + Send + Sync + 'static,
+  V: Clone + Send + Sync + 'static,
+{
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<K, V> Copy for QueryCache<K, V>
+where
+  K: Ord + Clone + Send + Sync + 'static,
+  V: Clone + Send + Sync + 'static,
+{
+}
+
+impl<K, V> QueryCache<K, V>
+where
+  K: Ord + Clone + Send + Sync + 'static,
+  V: Clone + Send + Sync + 'static,
+{
+  pub fn new(ttl_millis: i64, max_entries: usize) -> Self {
+    Self { entries: RwSignal::new(BTreeMap::new()), ttl_millis, max_entries }
+  }
+
+  fn now() -> i64 {
+    jiff::Timestamp::now().as_millisecond()
+  }
+
+  pub fn get(&self, key: &K) -> Option<(i64, V)> {
+    let now = Self::now();
+    self.entries.with_untracked(|e| e.get(key).filter(|(t, _)| now - t < self.ttl_millis).cloned())
+  }
+
+  // `fetched` is when the response left the server, so a page put back after rendering keeps its original age
+  pub fn insert(&self, key: K, fetched: i64, value: V) {
+    let now = Self::now();
+    let (ttl_millis, max_entries) = (self.ttl_millis, self.max_entries);
+    self.entries.update(move |e| {
+      e.insert(key, (fetched, value));
+      e.retain(|_, (t, _)| now - *t < ttl_millis);
+      // oldest first until back under the bound
+      while e.len() > max_entries {
+        let Some(oldest) = e.iter().min_by_key(|(_, (t, _))| *t).map(|(k, _)| k.clone()) else {
+          break;
+        };
+        e.remove(&oldest);
+      }
+    });
+  }
+
+  pub fn remove(&self, key: &K) {
+    let key = key.clone();
+    self.entries.update(move |e| {
+      e.remove(&key);
+    });
+  }
+
+  // drops every entry the predicate matches, leaving the signal untouched when nothing does
+  pub fn invalidate(&self, stale: impl Fn(&K, &V) -> bool) {
+    if self.entries.with_untracked(|e| e.iter().any(|(k, (_, v))| stale(k, v))) {
+      self.entries.update(|e| e.retain(|k, (_, v)| !stale(k, v)));
+    }
+  }
+
+  pub fn clear(&self) {
+    self.entries.update(|e| e.clear());
+  }
+}
+
+// page index, form and jwt
+pub type ListingCache = QueryCache<(usize, GetPosts, Option<String>), GetPostsResponse>;
+pub type PostCache = QueryCache<(GetPost, Option<String>), GetPostResponse>;
+pub type CommentsCache = QueryCache<(GetComments, Option<String>), GetCommentsResponse>;
+
+#[derive(Clone, Copy)]
+pub struct QueryCaches {
+  pub listing: ListingCache,
+  pub post: PostCache,
+  pub comments: CommentsCache,
+}
+
+impl QueryCaches {
+  pub fn new() -> Self {
+    Self { listing: QueryCache::new(15 * 60 * 1000, 64), post: QueryCache::new(15 * 60 * 1000, 32), comments: QueryCache::new(15 * 60 * 1000, 32) }
+  }
+
+  pub fn clear(&self) {
+    self.listing.clear();
+    self.post.clear();
+    self.comments.clear();
+  }
+
+  pub fn invalidate_post(&self, post_id: PostId) {
+    self.listing.invalidate(|_, r| r.posts.iter().any(|p| p.post.id == post_id));
+    self.post.invalidate(|_, r| r.post_view.post.id == post_id);
+  }
+
+  pub fn invalidate_comment(&self, comment_id: CommentId) {
+    self.comments.invalidate(|_, r| r.comments.iter().any(|c| c.comment.id == comment_id));
+  }
+
+  pub fn invalidate_post_comments(&self, post_id: PostId) {
+    self.comments.invalidate(|(f, _), _| f.post_id == Some(post_id));
+  }
+
+  // blocking hides everything the person wrote, so any listing or thread they appear in is stale
+  pub fn invalidate_person(&self, person_id: PersonId) {
+    self.listing.invalidate(|_, r| r.posts.iter().any(|p| p.creator.id == person_id));
+    self.post.invalidate(|_, r| r.post_view.creator.id == person_id);
+    self.comments.invalidate(|_, r| r.comments.iter().any(|c| c.creator.id == person_id));
+  }
+}
+
+// runs `stale` against the caches once a mutation has gone through
+pub fn invalidate_on_success<T>(result: LemmyAppResult<T>, stale: impl FnOnce(QueryCaches)) -> LemmyAppResult<T> {
+  if result.is_ok()
+    && let Some(caches) = use_context::<QueryCaches>()
+  {
+    stale(caches);
+  }
+  result
+}
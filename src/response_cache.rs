@@ -0,0 +1,223 @@
+use crate::errors::{LemmyAppError, LemmyAppErrorType, LemmyAppResult};
+use serde::{Serialize, de::DeserializeOwned};
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::{LazyLock, Mutex, OnceLock},
+  time::{Duration, Instant},
+};
+
+// how long and how much the server keeps of what anonymous visitors are shown. every logged out visitor sees the
+// same site, listings and threads, so a few seconds of reuse takes most of the load off the instance
+#[derive(Clone, Debug)]
+pub struct CacheConfig {
+  // zero turns the cache off
+  pub ttl: Duration,
+  pub max_entries: usize,
+  // responses are kept as json, this bounds their total length
+  pub max_bytes: usize,
+}
+
+impl Default for CacheConfig {
+  fn default() -> Self {
+    Self { ttl: Duration::from_secs(10), max_entries: 1000, max_bytes: 64 * 1024 * 1024 }
+  }
+}
+
+impl CacheConfig {
+  // the defaults, with AOS_CACHE_TTL in seconds, AOS_CACHE_ENTRIES and AOS_CACHE_BYTES taking precedence
+  pub fn from_env() -> Self {
+    let number = |name: &str| std::env::var(name).ok().and_then(|v| v.trim().parse::<u64>().ok());
+    let default = Self::default();
+    Self {
+      ttl: number("AOS_CACHE_TTL").map(Duration::from_secs).unwrap_or(default.ttl),
+      max_entries: number("AOS_CACHE_ENTRIES").map(|n| n as usize).unwrap_or(default.max_entries),
+      max_bytes: number("AOS_CACHE_BYTES").map(|n| n as usize).unwrap_or(default.max_bytes),
+    }
+  }
+}
+
+static CONFIG: OnceLock<CacheConfig> = OnceLock::new();
+
+pub fn configure_cache(config: CacheConfig) -> LemmyAppResult<()> {
+  CONFIG
+    .set(config)
+    .map_err(|_| LemmyAppError { error_type: LemmyAppErrorType::InternalServerError, content: "response cache already configured".into() })
+}
+
+fn config() -> &'static CacheConfig {
+  CONFIG.get_or_init(CacheConfig::from_env)
+}
+
+pub fn enabled() -> bool {
+  !config().ttl.is_zero()
+}
+
+struct Entry {
+  json: String,
+  stored: Instant,
+  // which of the key's places in the order is its current one
+  seq: u64,
+}
+
+#[derive(Default)]
+struct Cache {
+  entries: HashMap<String, Entry>,
+  // keys oldest first, so expired and evicted entries come off the front. a key stored again or dropped early leaves
+  // its old place behind, which is skipped when it comes up
+  order: VecDeque<(u64, String)>,
+  seq: u64,
+  bytes: usize,
+}
+
+impl Cache {
+  fn remove(&mut self, key: &str) {
+    if let Some(e) = self.entries.remove(key) {
+      self.bytes -= e.json.len();
+    }
+  }
+
+  // expired entries go first, then the oldest until the new one fits
+  fn make_room(&mut self, config: &CacheConfig, incoming: usize, now: Instant) {
+    while let Some((seq, key)) = self.order.front() {
+      let current = self.entries.get(key).filter(|e| e.seq == *seq);
+      let full = self.entries.len() >= config.max_entries || self.bytes + incoming > config.max_bytes;
+      if current.is_some_and(|e| !full && now.duration_since(e.stored) < config.ttl) {
+        break;
+      }
+      let stale = current.is_none();
+      let (_, key) = self.order.pop_front().unwrap_or_default();
+      if !stale {
+        self.remove(&key);
+      }
+    }
+  }
+
+  fn get(&mut self, config: &CacheConfig, key: &str, now: Instant) -> Option<&str> {
+    let fresh = self.entries.get(key).map(|e| now.duration_since(e.stored) < config.ttl)?;
+    if !fresh {
+      self.remove(key);
+      return None;
+    }
+    self.entries.get(key).map(|e| e.json.as_str())
+  }
+
+  fn put(&mut self, config: &CacheConfig, key: String, json: String, now: Instant) {
+    if config.ttl.is_zero() || config.max_entries == 0 || json.len() > config.max_bytes {
+      return;
+    }
+    self.remove(&key);
+    self.make_room(config, json.len(), now);
+    self.seq += 1;
+    self.bytes += json.len();
+    self.order.push_back((self.seq, key.clone()));
+    self.entries.insert(key, Entry { json, stored: now, seq: self.seq });
+  }
+}
+
+static CACHE: LazyLock<Mutex<Cache>> = LazyLock::new(|| Mutex::new(Cache::default()));
+
+// the site form carries the time only to get past browser caches, here it would make every second a new entry
+pub fn key<Form: Serialize>(instance: &str, path: &str, form: &Form) -> String {
+  let mut form = serde_json::to_value(form).unwrap_or_default();
+  if path == "site"
+    && let Some(f) = form.as_object_mut()
+  {
+    f.remove("t");
+  }
+  format!("{} {} {}", instance, path, form)
+}
+
+pub fn get<Response: DeserializeOwned>(key: &str) -> Option<Response> {
+  let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+  cache.get(config(), key, Instant::now()).and_then(|json| serde_json::from_str(json).ok())
+}
+
+pub fn put<Response: Serialize>(key: String, response: &Response) {
+  let Ok(json) = serde_json::to_string(response) else {
+    return;
+  };
+  CACHE.lock().unwrap_or_else(|e| e.into_inner()).put(config(), key, json, Instant::now());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn small(ttl: Duration) -> CacheConfig {
+    CacheConfig { ttl, max_entries: 3, max_bytes: 10 }
+  }
+
+  fn secs(start: Instant, s: u64) -> Instant {
+    start + Duration::from_secs(s)
+  }
+
+  #[test]
+  fn entries_expire() {
+    let config = small(Duration::from_secs(10));
+    let (start, mut cache) = (Instant::now(), Cache::default());
+    cache.put(&config, "a".into(), "1".into(), start);
+    assert_eq!(cache.get(&config, "a", secs(start, 9)), Some("1"));
+    assert_eq!(cache.get(&config, "a", secs(start, 10)), None);
+    assert_eq!((cache.entries.len(), cache.bytes), (0, 0));
+
+    // expired entries make room before fresh ones are evicted
+    cache.put(&config, "b".into(), "1".into(), start);
+    cache.put(&config, "c".into(), "1".into(), secs(start, 5));
+    cache.put(&config, "d".into(), "1".into(), secs(start, 11));
+    assert_eq!(cache.entries.len(), 2);
+    assert_eq!(cache.get(&config, "c", secs(start, 11)), Some("1"));
+
+    let off = small(Duration::ZERO);
+    cache.put(&off, "e".into(), "1".into(), start);
+    assert_eq!(cache.entries.get("e").map(|e| e.json.as_str()), None);
+  }
+
+  #[test]
+  fn oldest_make_room_for_entries() {
+    let config = small(Duration::from_secs(60));
+    let (start, mut cache) = (Instant::now(), Cache::default());
+    for (s, k) in ["a", "b", "c", "d"].into_iter().enumerate() {
+      cache.put(&config, k.into(), "1".into(), secs(start, s as u64));
+    }
+    let now = secs(start, 4);
+    assert_eq!(cache.get(&config, "a", now), None);
+    assert_eq!(cache.get(&config, "d", now), Some("1"));
+    assert_eq!((cache.entries.len(), cache.bytes), (3, 3));
+
+    // storing a key again moves it to the back
+    cache.put(&config, "b".into(), "1".into(), now);
+    cache.put(&config, "e".into(), "1".into(), secs(start, 5));
+    assert_eq!(cache.get(&config, "c", now), None);
+    assert_eq!(cache.get(&config, "b", now), Some("1"));
+  }
+
+  #[test]
+  fn oldest_make_room_for_bytes() {
+    let config = small(Duration::from_secs(60));
+    let (start, mut cache) = (Instant::now(), Cache::default());
+    cache.put(&config, "a".into(), "1234".into(), start);
+    cache.put(&config, "b".into(), "1234".into(), secs(start, 1));
+    cache.put(&config, "c".into(), "123".into(), secs(start, 2));
+    let now = secs(start, 3);
+    assert_eq!(cache.get(&config, "a", now), None);
+    assert_eq!(cache.get(&config, "b", now), Some("1234"));
+    assert_eq!(cache.bytes, 7);
+
+    // too large to keep at all
+    cache.put(&config, "d".into(), "12345678901".into(), now);
+    assert_eq!(cache.get(&config, "d", now), None);
+    assert_eq!(cache.bytes, 7);
+
+    // replacing an entry counts only its new length
+    cache.put(&config, "b".into(), "1".into(), now);
+    assert_eq!(cache.bytes, 4);
+  }
+
+  #[test]
+  fn site_keys_ignore_the_time() {
+    let at = |t: u64| serde_json::json!({ "t": t });
+    assert_eq!(key("lemmy.world", "site", &at(1000)), key("lemmy.world", "site", &at(2000)));
+    assert_ne!(key("lemmy.world", "post", &at(1000)), key("lemmy.world", "post", &at(2000)));
+    assert_ne!(key("lemmy.world", "site", &at(1000)), key("lemmy.ml", "site", &at(1000)));
+  }
+}
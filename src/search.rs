@@ -45,15 +45,11 @@ pub fn Search() -> impl IntoView {
     };
     use web_sys::Event;
 
+    let shared_db = expect_context::<SharedIndexedDb>();
+    let location = use_location();
     let on_scroll = move |_e: Event| {
       if let Some(se) = on_scroll_element.get() {
-        #[cfg(not(feature = "ssr"))]
-        spawn_local_scoped_with_cancellation(async move {
-          let query_params = query.get();
-          if let Ok(d) = IndexedDb::new().await {
-            let _ = d.set(&ScrollPositionKey { path: use_location().pathname.get(), query: query_params.to_query_string() }, &se.scroll_left()).await;
-          }
-        });
+        shared_db.save_scroll_position(ScrollPositionKey { path: location.pathname.get_untracked(), query: query.get_untracked().to_query_string() }, se.scroll_left());
       }
     };
 
@@ -0,0 +1,226 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// used when neither the deployment nor the visitor has chosen an instance
+pub const FALLBACK_INSTANCE: &str = "lemmy.world";
+
+// how an AOS deployment is run, read from a json file with AOS_* environment variables taking precedence over it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+  pub default_instance: String,
+  // empty lets visitors pick any instance, otherwise the deployment only serves these
+  pub allowed_instances: Vec<String>,
+  pub worker_threads: usize,
+  pub thread_stack_size: usize,
+  // one deployment can front several instances, each under its own host name
+  pub hosts: HashMap<String, Tenant>,
+}
+
+impl Default for ServerConfig {
+  fn default() -> Self {
+    Self {
+      default_instance: FALLBACK_INSTANCE.to_owned(),
+      allowed_instances: vec![],
+      worker_threads: 4,
+      thread_stack_size: 8 * 1024 * 1024,
+      hosts: HashMap::new(),
+    }
+  }
+}
+
+// what a host name the deployment answers on stands for: the instance it fronts and how it is branded
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tenant {
+  pub instance: String,
+  // shown in place of AOS
+  pub brand: Option<String>,
+  pub logo: Option<String>,
+  // visitors cannot switch this host to another instance
+  pub locked: bool,
+}
+
+// the instances a deployment serves, sent along to the browser so it can turn the others away before asking them
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Allowlist(pub Vec<String>);
+
+impl ServerConfig {
+  pub fn permits(&self, instance: &str) -> bool {
+    self.allowed_instances.is_empty() || self.allowed_instances.iter().any(|a| a.eq_ignore_ascii_case(instance))
+  }
+
+  // the tenant for a request's Host header, ports aside, or the deployment's default
+  pub fn tenant_for(&self, host: Option<&str>) -> Tenant {
+    host
+      .and_then(|h| self.hosts.get(&h.rsplit_once(':').map_or(h, |(h, _)| h).to_lowercase()))
+      .cloned()
+      .unwrap_or_else(|| Tenant { instance: self.default_instance.clone(), ..Tenant::default() })
+  }
+
+  // the instance a visitor asked for if the tenant lets them choose and this deployment serves it, the tenant's own
+  // otherwise
+  pub fn instance_for(&self, tenant: &Tenant, requested: Option<String>) -> String {
+    requested.filter(|i| !tenant.locked && !i.is_empty() && self.permits(i)).unwrap_or_else(|| tenant.instance.clone())
+  }
+
+  // AOS_CONFIG names the file, aos.json in the working directory is used if it exists. AOS_DEFAULT_INSTANCE,
+  // AOS_ALLOWED_INSTANCES (comma separated), AOS_WORKER_THREADS and AOS_THREAD_STACK_SIZE override what it says
+  #[cfg(feature = "ssr")]
+  pub fn load() -> Result<Self, String> {
+    let named = std::env::var("AOS_CONFIG").ok();
+    let path = named.clone().unwrap_or_else(|| "aos.json".to_owned());
+    let mut config = match std::fs::read_to_string(&path) {
+      Ok(s) => serde_json::from_str::<Self>(&s).map_err(|e| format!("{}: {}", path, e))?,
+      Err(e) if named.is_none() && e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+      Err(e) => return Err(format!("{}: {}", path, e)),
+    };
+    config.apply_env(|name| std::env::var(name).ok())?;
+    config.check()?;
+    Ok(config)
+  }
+
+  fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+    let number = |name: &str| var(name).map(|v| v.trim().parse::<usize>().map_err(|_| format!("{} is not a number: {}", name, v))).transpose();
+    if let Some(i) = var("AOS_DEFAULT_INSTANCE") {
+      self.default_instance = i;
+    }
+    if let Some(a) = var("AOS_ALLOWED_INSTANCES") {
+      self.allowed_instances = a.split(',').map(|i| i.trim().to_owned()).filter(|i| !i.is_empty()).collect();
+    }
+    if let Some(n) = number("AOS_WORKER_THREADS")? {
+      self.worker_threads = n;
+    }
+    if let Some(n) = number("AOS_THREAD_STACK_SIZE")? {
+      self.thread_stack_size = n;
+    }
+    Ok(())
+  }
+
+  // instances are kept as bare hosts, and a locked deployment has to allow its own defaults
+  fn check(&mut self) -> Result<(), String> {
+    let host = |i: &str| crate::instances::normalize_host(i).ok_or_else(|| format!("{} is not a valid instance address", i));
+    self.default_instance = host(&self.default_instance)?;
+    self.allowed_instances = self.allowed_instances.iter().map(|i| host(i)).collect::<Result<_, _>>()?;
+    if !self.permits(&self.default_instance) {
+      return Err(format!("the default instance {} is not one of the allowed instances", self.default_instance));
+    }
+    let mut hosts = HashMap::new();
+    for (name, mut tenant) in std::mem::take(&mut self.hosts) {
+      tenant.instance = host(&tenant.instance)?;
+      if !self.permits(&tenant.instance) {
+        return Err(format!("{} fronts {}, which is not one of the allowed instances", name, tenant.instance));
+      }
+      hosts.insert(name.to_lowercase(), tenant);
+    }
+    self.hosts = hosts;
+    if self.worker_threads == 0 {
+      return Err("worker_threads must be at least 1".into());
+    }
+    Ok(())
+  }
+}
+
+#[cfg(feature = "ssr")]
+static SERVER_CONFIG: std::sync::OnceLock<ServerConfig> = std::sync::OnceLock::new();
+
+// set once by the server at startup, before anything is rendered
+#[cfg(feature = "ssr")]
+pub fn set_server_config(config: ServerConfig) {
+  _ = SERVER_CONFIG.set(config);
+}
+
+// the running deployment's config. the browser app has the defaults and the allowlist the server sent, if any
+pub fn server_config() -> ServerConfig {
+  #[cfg(feature = "ssr")]
+  return SERVER_CONFIG.get_or_init(ServerConfig::default).clone();
+  #[cfg(not(feature = "ssr"))]
+  {
+    use leptos::prelude::{GetUntracked, Resource, use_context};
+    let allowed_instances = use_context::<Resource<Allowlist>>().and_then(|a| a.get_untracked()).map(|a| a.0).unwrap_or_default();
+    ServerConfig { allowed_instances, ..ServerConfig::default() }
+  }
+}
+
+// the tenant of the request being served. the server provides it for pages, server functions fall back to reading
+// the Host header themselves. the browser reads the one the server sent with the page
+pub fn current_tenant() -> Tenant {
+  #[cfg(feature = "ssr")]
+  {
+    use leptos::prelude::use_context;
+    if let Some(tenant) = use_context::<Tenant>() {
+      return tenant;
+    }
+    let host = use_context::<http::request::Parts>().and_then(|p| p.headers.get(http::header::HOST).and_then(|h| h.to_str().ok().map(str::to_owned)));
+    return server_config().tenant_for(host.as_deref());
+  }
+  #[cfg(not(feature = "ssr"))]
+  {
+    use leptos::prelude::{GetUntracked, Resource, use_context};
+    use_context::<Resource<Tenant>>().and_then(|t| t.get_untracked()).unwrap_or_else(|| server_config().tenant_for(None))
+  }
+}
+
+// where a visitor's requests go, given the instance their cookie names
+pub fn visitor_instance(requested: Option<String>) -> String {
+  server_config().instance_for(&current_tenant(), requested)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+    move |name| vars.iter().find(|(n, _)| *n == name).map(|(_, v)| v.to_string())
+  }
+
+  #[test]
+  fn environment_overrides_file() {
+    let mut config: ServerConfig = serde_json::from_str(r#"{ "default_instance": "lemmy.ml", "worker_threads": 2 }"#).unwrap();
+    config.apply_env(env(&[("AOS_DEFAULT_INSTANCE", "https://Example.org/"), ("AOS_ALLOWED_INSTANCES", "example.org, lemmy.ml")])).unwrap();
+    config.check().unwrap();
+    assert_eq!(config.default_instance, "example.org");
+    assert_eq!(config.allowed_instances, vec!["example.org", "lemmy.ml"]);
+    assert_eq!(config.worker_threads, 2);
+    assert_eq!(config.thread_stack_size, ServerConfig::default().thread_stack_size);
+  }
+
+  #[test]
+  fn allowlist_decides_instance() {
+    let open = ServerConfig::default();
+    let tenant = open.tenant_for(None);
+    assert_eq!(open.instance_for(&tenant, Some("lemmy.ml".into())), "lemmy.ml");
+    assert_eq!(open.instance_for(&tenant, None), FALLBACK_INSTANCE);
+
+    let mut locked = ServerConfig { default_instance: "example.org".into(), allowed_instances: vec!["example.org".into()], ..Default::default() };
+    assert!(locked.check().is_ok());
+    assert_eq!(locked.instance_for(&locked.tenant_for(None), Some("lemmy.ml".into())), "example.org");
+
+    locked.default_instance = "lemmy.ml".into();
+    assert!(locked.check().is_err());
+  }
+
+  #[test]
+  fn hosts_pick_their_tenant() {
+    let mut config: ServerConfig = serde_json::from_str(
+      r#"{
+        "hosts": {
+          "ace.instance-a.org": { "instance": "instance-a.org", "brand": "Ace A" },
+          "ace.instance-b.org": { "instance": "instance-b.org", "locked": true }
+        }
+      }"#,
+    )
+    .unwrap();
+    config.check().unwrap();
+
+    let a = config.tenant_for(Some("ACE.instance-a.org:443"));
+    assert_eq!(a.instance, "instance-a.org");
+    assert_eq!(a.brand.as_deref(), Some("Ace A"));
+    assert_eq!(config.instance_for(&a, Some("lemmy.ml".into())), "lemmy.ml");
+
+    let b = config.tenant_for(Some("ace.instance-b.org"));
+    assert_eq!(config.instance_for(&b, Some("lemmy.ml".into())), "instance-b.org");
+
+    assert_eq!(config.tenant_for(Some("elsewhere.org")).instance, FALLBACK_INSTANCE);
+  }
+}
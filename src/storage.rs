@@ -0,0 +1,108 @@
+use crate::{
+  db::csr_indexed_db::*,
+  icon::{Icon, IconType::*},
+  nav::TopNav,
+};
+use leptos::{html::Div, prelude::*, task::spawn_local_scoped_with_cancellation};
+use leptos_meta::Title;
+use web_sys::MouseEvent;
+
+fn store_label(store: &str) -> &str {
+  match store {
+    "query_gets" => "Pages and responses",
+    "scroll_positions" => "Scroll positions",
+    s => s,
+  }
+}
+
+fn format_bytes(bytes: f64) -> String {
+  if bytes >= 1024.0 * 1024.0 {
+    format!("{:.1} MB", bytes / (1024.0 * 1024.0))
+  } else if bytes >= 1024.0 {
+    format!("{:.1} KB", bytes / 1024.0)
+  } else {
+    format!("{} B", bytes)
+  }
+}
+
+// what the browser reports for the whole origin, including anything stored outside the cache
+#[cfg(not(feature = "ssr"))]
+async fn estimate() -> Option<(f64, f64)> {
+  use web_sys::wasm_bindgen::JsCast;
+  let promise = window().navigator().storage().estimate().ok()?;
+  let e = wasm_bindgen_futures::JsFuture::from(promise).await.ok()?.unchecked_into::<web_sys::StorageEstimate>();
+  Some((e.get_usage()?, e.get_quota()?))
+}
+
+#[component]
+pub fn OfflineStorage() -> impl IntoView {
+  let usage: RwSignal<Vec<CacheUsage>> = RwSignal::new(vec![]);
+  let browser_usage: RwSignal<Option<(f64, f64)>> = RwSignal::new(None);
+  let refresh = RwSignal::new(0usize);
+  let on_scroll_element = NodeRef::<Div>::new();
+
+  #[cfg(not(feature = "ssr"))]
+  Effect::new(move |_| {
+    refresh.track();
+    spawn_local_scoped_with_cancellation(async move {
+      if let Ok(d) = IndexedDb::shared().await
+        && let Ok(u) = d.usage().await
+      {
+        usage.set(u);
+      }
+      browser_usage.set(estimate().await);
+    });
+  });
+
+  let on_clear = move |_e: MouseEvent| {
+    if !window().confirm_with_message("Clear cached pages and scroll positions? Drafts and queued actions are kept.").unwrap_or(false) {
+      return;
+    }
+    #[cfg(not(feature = "ssr"))]
+    spawn_local_scoped_with_cancellation(async move {
+      if let Ok(d) = IndexedDb::shared().await {
+        let _ = d.clear_cache().await;
+      }
+      refresh.update(|r| *r += 1);
+    });
+  };
+
+  view! {
+    <main class="flex flex-col">
+      <Title text="Offline storage" />
+      <TopNav scroll_element={on_scroll_element.into()} />
+      <div class="p-3 mx-auto w-full max-w-screen-md" node_ref={on_scroll_element}>
+        <div class="flex justify-between items-center mb-4">
+          <span class="text-2xl font-extrabold">"Offline storage"</span>
+          <button type="button" class="btn btn-neutral btn-sm" on:click={on_clear}>
+            <Icon icon={Eraser} />
+            "Clear offline cache"
+          </button>
+        </div>
+        <table class="table">
+          <tbody>
+            <For each={move || usage.get()} key={|u| (u.store.clone(), u.entries, u.bytes)} let:u>
+              <tr>
+                <td>{store_label(&u.store).to_string()}</td>
+                <td>{format!("{} entries", u.entries)}</td>
+                <td>{format_bytes(u.bytes as f64)}</td>
+              </tr>
+            </For>
+          </tbody>
+        </table>
+        {move || {
+          browser_usage
+            .get()
+            .map(|(used, quota)| {
+              view! {
+                <div class="mt-4 text-sm text-base-content/70">
+                  {format!("Browser storage in use: {} of {}", format_bytes(used), format_bytes(quota))}
+                </div>
+                <progress class="w-full progress" value={used} max={quota} />
+              }
+            })
+        }}
+      </div>
+    </main>
+  }
+}
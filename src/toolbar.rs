@@ -1,9 +1,13 @@
 use crate::{
-  OnlineSetter, ReadInstanceCookie,
+  ReadInstanceCookie,
   client::*,
   db::csr_indexed_db::*,
+  emoji::custom_emojis,
+  entities::Entities,
   errors::{LemmyAppError, LemmyAppErrorType},
   icon::{IconType::*, *},
+  markdown::*,
+  pending::is_queued,
 };
 use lemmy_api_common::{lemmy_db_views::structs::*, person::*, post::*, site::GetSiteResponse};
 use leptos::{html::Img, prelude::*, task::*};
@@ -104,8 +108,7 @@ pub fn PostToolbar(
 ) -> impl IntoView {
   let ssr_site = expect_context::<Resource<Result<GetSiteResponse, LemmyAppError>>>();
   let ReadInstanceCookie(get_instance_cookie) = expect_context::<ReadInstanceCookie>();
-  let online = expect_context::<RwSignal<OnlineSetter>>();
-  let post_view = RwSignal::new(post_view.get());
+  let post_view = expect_context::<Entities>().post(post_view.get());
   let vote_action = ServerAction::<VotePostFn>::new();
 
   let on_vote_submit = move |e: MouseEvent, score: i16| {
@@ -117,6 +120,9 @@ pub fn PostToolbar(
         Ok(o) => {
           post_view.set(o.post_view);
         }
+        Err(_) if is_queued(&result) => {
+          post_view.update(|pv| pv.my_vote = Some(score));
+        }
         Err(_e) => {}
       }
     });
@@ -144,6 +150,9 @@ pub fn PostToolbar(
         Ok(o) => {
           post_view.set(o.post_view);
         }
+        Err(_) if is_queued(&result) => {
+          post_view.update(|pv| pv.saved = !pv.saved);
+        }
         Err(_e) => {}
       }
     });
@@ -212,27 +221,7 @@ pub fn PostToolbar(
   };
 
   let title = post_view.get().post.name.clone();
-  let mut options = pulldown_cmark::Options::empty();
-  options.insert(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
-  options.insert(pulldown_cmark::Options::ENABLE_TABLES);
-  options.insert(pulldown_cmark::Options::ENABLE_SUPERSCRIPT);
-  options.insert(pulldown_cmark::Options::ENABLE_SUBSCRIPT);
-  options.insert(pulldown_cmark::Options::ENABLE_CONTAINER_EXTENSIONS);
-  let parser = pulldown_cmark::Parser::new_ext(&title, options);
-
-  let custom = parser.map(|event| match event {
-    pulldown_cmark::Event::Html(text) => {
-      let er = format!("<p>{}</p>", html_escape::encode_safe(&text).to_string());
-      pulldown_cmark::Event::Html(er.into())
-    }
-    pulldown_cmark::Event::InlineHtml(text) => {
-      let er = html_escape::encode_safe(&text).to_string();
-      pulldown_cmark::Event::InlineHtml(er.into())
-    }
-    _ => event,
-  });
-  let mut title_encoded = String::new();
-  pulldown_cmark::html::push_html(&mut title_encoded, custom);
+  let title_encoded = markdown_to_html(&title, Profile::Title, custom_emojis(ssr_site));
 
   let community_title = if post_view.get().community.local {
     format!("{}", post_view.get().community.name)
@@ -293,10 +282,10 @@ pub fn PostToolbar(
                           format!(
                             "{}{}",
                             { if Some(1) == post_view.get().my_vote { "text-secondary" } else { "" } },
-                            { if !logged_in.get() || !online.get().0 { " text-base-content/50" } else { " hover:text-secondary/50" } },
+                            { if !logged_in.get() { " text-base-content/50" } else { " hover:text-secondary/50" } },
                           )
                         }}
-                        disabled={move || !logged_in.get() || !online.get().0}
+                        disabled={move || !logged_in.get()}
                         title="Up vote"
                       >
                         <Icon icon={Upvote} />
@@ -313,10 +302,10 @@ pub fn PostToolbar(
                           format!(
                             "{}{}",
                             { if Some(-1) == post_view.get().my_vote { "text-primary" } else { "" } },
-                            { if !logged_in.get() || !online.get().0 { " text-base-content/50" } else { " hover:text-primary/50" } },
+                            { if !logged_in.get() { " text-base-content/50" } else { " hover:text-primary/50" } },
                           )
                         }}
-                        disabled={move || !logged_in.get() || !online.get().0}
+                        disabled={move || !logged_in.get()}
                         title="Down vote"
                       >
                         <Icon icon={Downvote} />
@@ -359,10 +348,10 @@ pub fn PostToolbar(
                           format!(
                             "{}{}",
                             { if post_view.get().saved { "text-accent" } else { "" } },
-                            { if !logged_in.get() || !online.get().0 { " text-base-content/50" } else { " hover:text-accent/50" } },
+                            { if !logged_in.get() { " text-base-content/50" } else { " hover:text-accent/50" } },
                           )
                         }}
-                        disabled={move || !logged_in.get() || !online.get().0}
+                        disabled={move || !logged_in.get()}
                       >
                         <Icon icon={Save} />
                       </button>
@@ -371,16 +360,16 @@ pub fn PostToolbar(
                       class={move || {
                         format!(
                           "cursor-pointer{}",
-                          { if !logged_in.get() || !online.get().0 { " text-base-content/50" } else { " hover:text-accent/50" } },
+                          { if !logged_in.get() { " text-base-content/50" } else { " hover:text-accent/50" } },
                         )
                       }}
                       on:click={move |_| {
                         if let Some(id) = post_id.get_untracked() {
                           #[cfg(not(feature = "ssr"))]
                           spawn_local_scoped_with_cancellation(async move {
-                            if let Ok(d) = IndexedDb::new().await {
+                            if let Ok(d) = IndexedDb::shared().await {
                               if let Ok(Some(c)) = d
-                                .get(
+                                .get::<CommentDraftKey, CommentDraft>(
                                   &CommentDraftKey {
                                     comment_id: id,
                                     draft: Draft::Post,
@@ -388,7 +377,7 @@ pub fn PostToolbar(
                                 )
                                 .await
                               {
-                                content.set(c);
+                                content.set(c.value);
                               }
                             }
                           });
@@ -396,7 +385,7 @@ pub fn PostToolbar(
                         reply_show.update(|b| *b = !*b);
                       }}
                       title="Reply"
-                      disabled={move || !logged_in.get() || !online.get().0}
+                      disabled={move || !logged_in.get()}
                     >
                       <Icon icon={Reply} />
                     </button>
@@ -4,7 +4,7 @@ use crate::{
   nav::TopNav,
   // i18n::*,
 };
-use crate::{comment::Comment, db::csr_indexed_db::*, listing::Listing};
+use crate::{comment::Comment, db::csr_indexed_db::*, emoji::*, listing::Listing, markdown::*};
 use lemmy_api_common::{
   lemmy_db_schema::{
     ListingType, SortType, SubscribedType,
@@ -12,7 +12,7 @@ use lemmy_api_common::{
     newtypes::{InstanceId, PostId},
   },
   lemmy_db_views::structs::{CommentView, PostView},
-  person::GetPersonDetails,
+  person::{GetPersonDetails, GetPersonDetailsResponse},
   site::GetSiteResponse,
 };
 use leptos::{html::Div, prelude::*, task::*, *};
@@ -23,7 +23,7 @@ use web_sys::{MouseEvent, WheelEvent};
 #[component]
 pub fn User() -> impl IntoView {
   // let i18n = use_i18n();
-  let _ssr_site = expect_context::<Resource<Result<GetSiteResponse, LemmyAppError>>>();
+  let ssr_site = expect_context::<Resource<Result<GetSiteResponse, LemmyAppError>>>();
   let param = use_params_map();
   let ssr_name = move || param.get().get("name").unwrap_or("".into());
 
@@ -45,15 +45,11 @@ pub fn User() -> impl IntoView {
     };
     use web_sys::Event;
 
+    let shared_db = expect_context::<SharedIndexedDb>();
+    let location = use_location();
     let on_scroll = move |_e: Event| {
       if let Some(se) = on_scroll_element.get() {
-        #[cfg(not(feature = "ssr"))]
-        spawn_local_scoped_with_cancellation(async move {
-          let query_params = query.get();
-          if let Ok(d) = IndexedDb::new().await {
-            let _ = d.set(&ScrollPositionKey { path: use_location().pathname.get(), query: query_params.to_query_string() }, &se.scroll_left()).await;
-          }
-        });
+        shared_db.save_scroll_position(ScrollPositionKey { path: location.pathname.get_untracked(), query: query.get_untracked().to_query_string() }, se.scroll_left());
       }
     };
 
@@ -81,6 +77,9 @@ pub fn User() -> impl IntoView {
     );
   }
 
+  // a fresh response arriving after a cached copy was shown
+  let fresh_user = RwSignal::new(None::<(String, GetPersonDetailsResponse)>);
+
   let user_resource = Resource::new(
     move || ssr_name(),
     move |name| async move {
@@ -93,7 +92,8 @@ pub fn User() -> impl IntoView {
         person_id: None,
         sort: Some(SortType::New),
       };
-      let result = match LemmyClient.get_user(form.clone()).await {
+      let fresh_name = name.clone();
+      let result = match LemmyClient.get_user_revalidating(form.clone(), Callback::new(move |o| fresh_user.set(Some((fresh_name.clone(), o))))).await {
         Ok(o) => Ok(Some(o)),
         Err(e) => Err(e),
       };
@@ -101,6 +101,14 @@ pub fn User() -> impl IntoView {
     },
   );
 
+  Effect::new(move |_| {
+    if let Some((name, o)) = fresh_user.get()
+      && name == untrack(ssr_name)
+    {
+      user_resource.set(Some(Ok(Some(o))));
+    }
+  });
+
   let now_in_millis = RwSignal::new(u64::try_from(jiff::Zoned::now().timestamp().as_millisecond()).unwrap_or(0));
   // let now_in_millis = RwSignal::new({
   //   #[cfg(not(feature = "ssr"))]
@@ -215,29 +223,7 @@ pub fn User() -> impl IntoView {
                       ap.sort_by(|a, b| a.post.post.published.cmp(&b.post.post.published).reverse());
                     });
                   let bio = if let Some(bio) = s.person_view.person.bio {
-                    let mut options = pulldown_cmark::Options::empty();
-                    options.insert(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
-                    options.insert(pulldown_cmark::Options::ENABLE_TABLES);
-                    options.insert(pulldown_cmark::Options::ENABLE_SUPERSCRIPT);
-                    options.insert(pulldown_cmark::Options::ENABLE_SUBSCRIPT);
-                    options.insert(pulldown_cmark::Options::ENABLE_CONTAINER_EXTENSIONS);
-                    options.insert(pulldown_cmark::Options::ENABLE_LINKIFY_LEMMY);
-                    options.insert(pulldown_cmark::Options::ENABLE_LINKIFY_HTTP);
-                    let parser = pulldown_cmark::Parser::new_ext(&bio, options);
-                    let custom = parser
-                      .map(|event| match event {
-                        pulldown_cmark::Event::Html(text) => {
-                          let er = format!("<p>{}</p>", html_escape::encode_safe(&text).to_string());
-                          pulldown_cmark::Event::Html(er.into())
-                        }
-                        pulldown_cmark::Event::InlineHtml(text) => {
-                          let er = html_escape::encode_safe(&text).to_string();
-                          pulldown_cmark::Event::InlineHtml(er.into())
-                        }
-                        _ => event,
-                      });
-                    let mut description_encoded = String::new();
-                    pulldown_cmark::html::push_html(&mut description_encoded, custom);
+                    let description_encoded = markdown_to_html(&bio, Profile::Bio, custom_emojis(ssr_site));
                     description_encoded
                   } else {
                     String::new()
@@ -308,7 +294,15 @@ pub fn User() -> impl IntoView {
                         }}
                       </div>
                       <div class="px-4 my-2">
-                        <div class="select-none prose" inner_html={bio} />
+                        <div
+                          class="select-none prose"
+                          on:click={move |e: MouseEvent| {
+                            if let Some(t) = e.target() {
+                              copy_code(&t);
+                            }
+                          }}
+                          inner_html={bio}
+                        />
                       </div>
                     </div>
 
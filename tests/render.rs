@@ -0,0 +1,70 @@
+// server rendering only: cargo test-ssr, the alias in .cargo/config.toml for
+// cargo test --no-default-features --features ssr
+#![cfg(feature = "ssr")]
+
+use any_spawner::Executor;
+use aos::{App, fixtures::Fixtures};
+use futures::StreamExt;
+use leptos::prelude::*;
+use leptos_router::location::RequestUrl;
+
+// renders the app at `path` in order, so every resource has been answered from the fixtures before the html comes back
+async fn render(path: &str) -> String {
+  _ = Executor::init_tokio();
+  let owner = Owner::new();
+  owner.set();
+  let (parts, _) = http::Request::builder().uri(path).header("cookie", "instance=fixtures.test").body(()).unwrap().into_parts();
+  provide_context(parts);
+  provide_context(leptos_axum::ResponseOptions::default());
+  provide_context(RequestUrl::new(path));
+  provide_context(Fixtures::recorded());
+  let html = view! { <App /> }.to_html_stream_in_order().collect::<String>().await;
+  drop(owner);
+  html
+}
+
+#[tokio::test]
+async fn overview_lists_posts() {
+  let html = render("/").await;
+  assert!(html.contains("Announcing the fixture release"));
+  assert!(html.contains("Which async runtime do you use?"));
+  assert!(html.contains("Fixtures"));
+}
+
+#[tokio::test]
+async fn post_shows_thread() {
+  let html = render("/p/10").await;
+  assert!(html.contains("Announcing the fixture release"));
+  assert!(html.contains("Great to see this land, the offline tests are a big help."));
+  assert!(html.contains("Thanks! More fixtures are on the way."));
+}
+
+#[tokio::test]
+async fn user_shows_posts_and_comments() {
+  let html = render("/u/alice").await;
+  assert!(html.contains("Announcing the fixture release"));
+  assert!(html.contains("Thanks! More fixtures are on the way."));
+}
+
+#[test]
+fn fixtures_match_the_api_types() {
+  use lemmy_api_common::{comment::GetCommentsResponse, person::GetPersonDetailsResponse, post::*, site::GetSiteResponse};
+
+  let fixtures = Fixtures::recorded();
+  assert!(fixtures.serve::<GetSiteResponse>("site").is_ok());
+  assert_eq!(fixtures.serve::<GetPostsResponse>("post/list").map(|r| r.posts.len()).ok(), Some(2));
+  assert!(fixtures.serve::<GetPostResponse>("post").is_ok());
+  assert_eq!(fixtures.serve::<GetCommentsResponse>("comment/list").map(|r| r.comments.len()).ok(), Some(3));
+  assert!(fixtures.serve::<GetPersonDetailsResponse>("user").is_ok());
+  assert!(fixtures.serve::<GetSiteResponse>("modlog").is_err());
+}
+
+#[tokio::test]
+async fn fixtures_answer_the_api() {
+  use aos::client::LemmyApi;
+  use lemmy_api_common::post::GetPosts;
+
+  let fixtures = Fixtures::recorded();
+  assert_eq!(fixtures.get_site().await.map(|s| s.version).ok().as_deref(), Some("0.19.18"));
+  assert_eq!(fixtures.list_posts(GetPosts::default()).await.map(|r| r.posts.len()).ok(), Some(2));
+}